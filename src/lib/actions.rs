@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Deserialize;
+use specs::prelude::*;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::lib::input::InputState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+/// Modifier keys that need to be held for a binding to trigger. Holding
+/// extra modifiers doesn't stop a binding from triggering.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Binding {
+    pub button: Button,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AxisBinding {
+    pub negative: Vec<Binding>,
+    pub positive: Vec<Binding>,
+}
+
+/// The contents of a bindings file, for example:
+///
+/// ```ron
+/// (
+///     actions: {
+///         "fire": [(button: Key(Space)), (button: Mouse(Left))],
+///         "save": [(button: Key(S), modifiers: (ctrl: true))],
+///     },
+///     axes: {
+///         "horizontal": (negative: [(button: Key(A))], positive: [(button: Key(D))]),
+///     },
+/// )
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    pub actions: HashMap<String, Vec<Binding>>,
+    pub axes: HashMap<String, AxisBinding>,
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(std::io::Error),
+    Parse(ron::de::Error),
+}
+
+impl std::fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BindingsError::Io(e) => write!(f, "unable to read bindings: {}", e),
+            BindingsError::Parse(e) => write!(f, "unable to parse bindings: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {}
+
+impl From<std::io::Error> for BindingsError {
+    fn from(e: std::io::Error) -> Self {
+        BindingsError::Io(e)
+    }
+}
+
+impl From<ron::de::Error> for BindingsError {
+    fn from(e: ron::de::Error) -> Self {
+        BindingsError::Parse(e)
+    }
+}
+
+/// Maps named actions and axes to the buttons bound to them. Systems query
+/// it together with the `InputState`.
+#[derive(Debug, Default)]
+pub struct ActionMap {
+    bindings: Bindings,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+impl ActionMap {
+    pub fn new(bindings: Bindings) -> Self {
+        Self { bindings, path: None, modified: None }
+    }
+
+    /// Loads the bindings from a ron file. The file can later be reloaded
+    /// with `reload`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, BindingsError> {
+        let path = path.as_ref().to_path_buf();
+        let modified = std::fs::metadata(&path)?.modified().ok();
+        let bindings = ron::de::from_str(&std::fs::read_to_string(&path)?)?;
+        Ok(Self { bindings, path: Some(path), modified })
+    }
+
+    /// Reloads the bindings file if it changed since it was last loaded,
+    /// returning whether it did. The current bindings are kept if the file
+    /// can't be loaded.
+    pub fn reload(&mut self) -> Result<bool, BindingsError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        let modified = std::fs::metadata(path)?.modified().ok();
+        if modified.is_some() && modified == self.modified {
+            return Ok(false);
+        }
+
+        self.bindings = ron::de::from_str(&std::fs::read_to_string(path)?)?;
+        self.modified = modified;
+        Ok(true)
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn is_down(&self, input: &InputState, action: &str) -> bool {
+        self.any_binding(input, action, button_down)
+    }
+
+    pub fn is_just_pressed(&self, input: &InputState, action: &str) -> bool {
+        self.any_binding(input, action, button_just_pressed)
+    }
+
+    pub fn is_just_released(&self, input: &InputState, action: &str) -> bool {
+        self.any_binding(input, action, button_just_released)
+    }
+
+    /// The value of an axis, from -1.0 to 1.0. Holding both directions
+    /// cancels out.
+    pub fn axis(&self, input: &InputState, axis: &str) -> f32 {
+        let axis = match self.bindings.axes.get(axis) {
+            Some(axis) => axis,
+            None => return 0.0,
+        };
+
+        let mut value = 0.0;
+        if any_triggered(input, &axis.negative, button_down) {
+            value -= 1.0;
+        }
+        if any_triggered(input, &axis.positive, button_down) {
+            value += 1.0;
+        }
+        value
+    }
+
+    fn any_binding(&self, input: &InputState, action: &str, test: fn(&InputState, Button) -> bool) -> bool {
+        self.bindings.actions.get(action)
+            .map_or(false, |bindings| any_triggered(input, bindings, test))
+    }
+}
+
+impl std::str::FromStr for ActionMap {
+    type Err = BindingsError;
+
+    /// Parses bindings from ron, like the contents of a bindings file.
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(ron::de::from_str(source)?))
+    }
+}
+
+fn any_triggered(input: &InputState, bindings: &[Binding], test: fn(&InputState, Button) -> bool) -> bool {
+    bindings.iter().any(|binding| modifiers_held(input, binding.modifiers) && test(input, binding.button))
+}
+
+fn modifiers_held(input: &InputState, required: Modifiers) -> bool {
+    let held = input.modifiers();
+    (!required.shift || held.shift)
+        && (!required.ctrl || held.ctrl)
+        && (!required.alt || held.alt)
+        && (!required.logo || held.logo)
+}
+
+fn button_down(input: &InputState, button: Button) -> bool {
+    match button {
+        Button::Key(key) => input.is_key_down(key),
+        Button::Mouse(button) => input.is_mouse_down(button),
+    }
+}
+
+fn button_just_pressed(input: &InputState, button: Button) -> bool {
+    match button {
+        Button::Key(key) => input.is_key_just_pressed(key),
+        Button::Mouse(button) => input.is_mouse_just_pressed(button),
+    }
+}
+
+fn button_just_released(input: &InputState, button: Button) -> bool {
+    match button {
+        Button::Key(key) => input.is_key_just_released(key),
+        Button::Mouse(button) => input.is_mouse_just_released(button),
+    }
+}
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Checks the bindings file for changes about once a second.
+pub struct ReloadBindingsSystem {
+    last_check: Instant,
+}
+
+impl Default for ReloadBindingsSystem {
+    fn default() -> Self {
+        Self { last_check: Instant::now() }
+    }
+}
+
+impl<'a> System<'a> for ReloadBindingsSystem {
+    type SystemData = Write<'a, ActionMap>;
+
+    fn run(&mut self, mut action_map: Self::SystemData) {
+        if self.last_check.elapsed() < RELOAD_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        match action_map.reload() {
+            Ok(true) => info!("Reloaded bindings"),
+            Ok(false) => {}
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::ModifiersState;
+
+    const BINDINGS: &str = r#"(
+        actions: {
+            "fire": [(button: Key(Space)), (button: Mouse(Left))],
+            "save": [(button: Key(S), modifiers: (ctrl: true))],
+        },
+        axes: {
+            "horizontal": (
+                negative: [(button: Key(A)), (button: Key(Left))],
+                positive: [(button: Key(D)), (button: Key(Right))],
+            ),
+        },
+    )"#;
+
+    fn action_map() -> ActionMap {
+        BINDINGS.parse::<ActionMap>().unwrap()
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let bindings = action_map().bindings;
+        assert_eq!(bindings.actions["fire"].len(), 2);
+        assert_eq!(bindings.actions["save"][0].modifiers, Modifiers { ctrl: true, ..Modifiers::default() });
+        assert_eq!(bindings.axes["horizontal"].positive[1].button, Button::Key(VirtualKeyCode::Right));
+    }
+
+    #[test]
+    fn any_bound_button_triggers_action() {
+        let action_map = action_map();
+        let mut input = InputState::default();
+        assert!(!action_map.is_down(&input, "fire"));
+
+        input.press_mouse(MouseButton::Left);
+        assert!(action_map.is_down(&input, "fire"));
+        assert!(action_map.is_just_pressed(&input, "fire"));
+    }
+
+    #[test]
+    fn modifiers_are_required() {
+        let action_map = action_map();
+        let mut input = InputState::default();
+        input.press_key(VirtualKeyCode::S);
+        assert!(!action_map.is_down(&input, "save"));
+
+        input.set_modifiers(ModifiersState { ctrl: true, ..ModifiersState::default() });
+        assert!(action_map.is_down(&input, "save"));
+    }
+
+    #[test]
+    fn axis_values() {
+        let action_map = action_map();
+        let mut input = InputState::default();
+        assert_eq!(action_map.axis(&input, "horizontal"), 0.0);
+
+        input.press_key(VirtualKeyCode::Left);
+        assert_eq!(action_map.axis(&input, "horizontal"), -1.0);
+
+        input.press_key(VirtualKeyCode::D);
+        assert_eq!(action_map.axis(&input, "horizontal"), 0.0);
+
+        input.release_key(VirtualKeyCode::Left);
+        assert_eq!(action_map.axis(&input, "horizontal"), 1.0);
+    }
+
+    #[test]
+    fn unknown_actions_are_never_down() {
+        let action_map = action_map();
+        let input = InputState::default();
+        assert!(!action_map.is_down(&input, "jump"));
+        assert_eq!(action_map.axis(&input, "vertical"), 0.0);
+    }
+}
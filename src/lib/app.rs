@@ -0,0 +1,394 @@
+use std::time::{Duration, Instant};
+
+use specs::{prelude::*, shrev::EventChannel};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::Window,
+};
+
+use crate::lib::{
+    camera::{Bounds, Camera2d, LookAtCamera},
+    components::InterpolationAlpha,
+    controllers::CameraControllerSystem,
+    graphics::{DepthConfig, Graphics, Renderer},
+    input::{self, InputEvent, InputState},
+    shader::ShaderError,
+    systems::{MeshInstanceSystem, SnapshotSystem, TransformSystem},
+    time::{FrameTime, Time, Timestep, TimestepMode},
+};
+
+/// What the hooks get access to while the app is running.
+pub struct Context<'a> {
+    pub world: &'a mut World,
+    pub graphics: &'a mut Graphics,
+    pub window: &'a Window,
+    exit: &'a mut bool,
+}
+
+impl<'a> Context<'a> {
+    /// Closes the app once the current event has been handled.
+    pub fn exit(&mut self) {
+        *self.exit = true;
+    }
+}
+
+type Hook = Box<dyn FnMut(&mut Context)>;
+type RendererFactory = Box<dyn FnOnce(&mut Graphics) -> Result<Box<dyn Renderer>, ShaderError>>;
+
+#[derive(Default)]
+struct Hooks {
+    on_start: Option<Hook>,
+    on_fixed_update: Option<Hook>,
+    on_render: Option<Hook>,
+    on_resize: Option<Hook>,
+}
+
+fn call(hook: &mut Option<Hook>, context: &mut Context) {
+    if let Some(hook) = hook {
+        hook(context);
+    }
+}
+
+pub struct AppBuilder {
+    title: String,
+    timestep_mode: TimestepMode,
+    max_updates_per_frame: u32,
+    clear_color: wgpu::Color,
+    depth: Option<DepthConfig>,
+    sample_count: u32,
+    world: World,
+    dispatcher: DispatcherBuilder<'static, 'static>,
+    render_dispatcher: DispatcherBuilder<'static, 'static>,
+    renderers: Vec<RendererFactory>,
+    hooks: Hooks,
+}
+
+impl AppBuilder {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            timestep_mode: TimestepMode::Fixed(Duration::from_millis(20)),
+            max_updates_per_frame: 5,
+            clear_color: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+            depth: None,
+            sample_count: 1,
+            world: World::new(),
+            dispatcher: DispatcherBuilder::new()
+                .with(SnapshotSystem, "snapshot_system", &[])
+                .with_barrier(),
+            render_dispatcher: DispatcherBuilder::new()
+                .with(CameraControllerSystem, "camera_controller_system", &[])
+                .with(TransformSystem::default(), "transform_system", &[])
+                .with(MeshInstanceSystem, "mesh_instance_system", &["transform_system"]),
+            renderers: Vec::new(),
+            hooks: Hooks::default(),
+        }
+    }
+
+    /// Runs the systems at a fixed timestep of this length.
+    pub fn with_timestep(self, timestep: Duration) -> Self {
+        self.with_timestep_mode(TimestepMode::Fixed(timestep))
+    }
+
+    pub fn with_timestep_mode(mut self, mode: TimestepMode) -> Self {
+        self.timestep_mode = mode;
+        self
+    }
+
+    /// Caps how many times the systems can run in a single frame, so a slow
+    /// frame doesn't cause even slower ones.
+    pub fn with_max_updates_per_frame(mut self, max_updates: u32) -> Self {
+        self.max_updates_per_frame = max_updates;
+        self
+    }
+
+    pub fn with_clear_color(mut self, clear_color: wgpu::Color) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    /// Gives `Graphics` a depth buffer, which every frame's render pass
+    /// uses.
+    pub fn with_depth(mut self, config: DepthConfig) -> Self {
+        self.depth = Some(config);
+        self
+    }
+
+    /// Draws with this many samples per pixel, to smooth out edges.
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn with_resource<R: Send + Sync + 'static>(mut self, resource: R) -> Self {
+        self.world.insert(resource);
+        self
+    }
+
+    pub fn with_system<S>(mut self, system: S, name: &str, dependencies: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'static,
+    {
+        self.dispatcher.add(system, name, dependencies);
+        self
+    }
+
+    /// Render systems run once per frame, after the fixed updates. They can
+    /// read the `InterpolationAlpha` resource to smooth out movement, and
+    /// should move things by `FrameTime` and the frame deltas of the
+    /// `InputState`. The `transform_system` and `mesh_instance_system` are
+    /// always there, for systems that need to run before or after them.
+    pub fn with_render_system<S>(mut self, system: S, name: &str, dependencies: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'static,
+    {
+        self.render_dispatcher.add(system, name, dependencies);
+        self
+    }
+
+    /// Renderers are created once the window exists, and draw in the order
+    /// they were added. If a renderer can't be created, `build` fails.
+    pub fn with_renderer<R, F>(mut self, factory: F) -> Self
+    where
+        R: Renderer + 'static,
+        F: FnOnce(&mut Graphics) -> Result<R, ShaderError> + 'static,
+    {
+        self.renderers.push(Box::new(move |graphics| {
+            factory(graphics).map(|renderer| Box::new(renderer) as Box<dyn Renderer>)
+        }));
+        self
+    }
+
+    /// Called once before the first update, after the systems are set up.
+    pub fn on_start<F: FnMut(&mut Context) + 'static>(mut self, hook: F) -> Self {
+        self.hooks.on_start = Some(Box::new(hook));
+        self
+    }
+
+    /// Called after every dispatch of the systems.
+    pub fn on_fixed_update<F: FnMut(&mut Context) + 'static>(mut self, hook: F) -> Self {
+        self.hooks.on_fixed_update = Some(Box::new(hook));
+        self
+    }
+
+    /// Called every frame, before the renderers are prepared.
+    pub fn on_render<F: FnMut(&mut Context) + 'static>(mut self, hook: F) -> Self {
+        self.hooks.on_render = Some(Box::new(hook));
+        self
+    }
+
+    /// Called after `Graphics` has been resized to match the window.
+    pub fn on_resize<F: FnMut(&mut Context) + 'static>(mut self, hook: F) -> Self {
+        self.hooks.on_resize = Some(Box::new(hook));
+        self
+    }
+
+    pub fn build(self) -> Result<App, ShaderError> {
+        let AppBuilder {
+            title,
+            timestep_mode,
+            max_updates_per_frame,
+            clear_color,
+            depth,
+            sample_count,
+            mut world,
+            dispatcher,
+            render_dispatcher,
+            renderers,
+            hooks,
+        } = self;
+
+        let event_loop = EventLoop::new();
+        let (mut graphics, window) = Graphics::windowed(&title, &event_loop);
+        graphics.set_sample_count(sample_count);
+        graphics.set_depth(depth);
+        let renderers = renderers.into_iter()
+            .map(|factory| factory(&mut graphics))
+            .collect::<Result<_, _>>()?;
+
+        let mut input = InputState::default();
+        input.set_hidpi_factor(window.hidpi_factor());
+        world.insert(input);
+        world.insert(EventChannel::<InputEvent>::new());
+        world.insert(InterpolationAlpha::default());
+        world.insert(Time::default());
+        world.insert(FrameTime::default());
+        world.entry::<Camera2d>().or_insert_with(Camera2d::default);
+        fit_view(&mut world, &graphics);
+
+        let mut dispatcher = dispatcher.build();
+        dispatcher.setup(&mut world);
+        let mut render_dispatcher = render_dispatcher.build();
+        render_dispatcher.setup(&mut world);
+
+        Ok(App {
+            event_loop,
+            window,
+            graphics,
+            world,
+            dispatcher,
+            render_dispatcher,
+            renderers,
+            hooks,
+            timestep: Timestep::new(timestep_mode).with_max_updates(max_updates_per_frame),
+            clear_color,
+        })
+    }
+
+    /// Builds and runs the app, exiting the process if it can't be built.
+    pub fn run(self) -> ! {
+        match self.build() {
+            Ok(app) => app.run(),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Fits the `Camera2d` to the screen, and the `Bounds` to what it sees. A
+/// `LookAtCamera` gets the screen's aspect ratio.
+fn fit_view(world: &mut World, graphics: &Graphics) {
+    world.write_resource::<Camera2d>().set_viewport(graphics.sc_desc.width as f32, graphics.sc_desc.height as f32);
+    update_bounds(world);
+    if let Some(mut camera) = world.try_fetch_mut::<LookAtCamera>() {
+        camera.aspect_ratio = graphics.aspect_ratio();
+    }
+}
+
+/// Fits the `Bounds` to what the `Camera2d` sees, as systems may have moved
+/// or zoomed it since.
+fn update_bounds(world: &mut World) {
+    let bounds = world.read_resource::<Camera2d>().bounds();
+    world.insert(bounds);
+}
+
+/// Owns the window and event loop, and runs the systems according to the
+/// timestep mode while drawing the renderers as often as possible.
+pub struct App {
+    event_loop: EventLoop<()>,
+    window: Window,
+    graphics: Graphics,
+    world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    render_dispatcher: Dispatcher<'static, 'static>,
+    renderers: Vec<Box<dyn Renderer>>,
+    hooks: Hooks,
+    timestep: Timestep,
+    clear_color: wgpu::Color,
+}
+
+impl App {
+    pub fn run(self) -> ! {
+        let App {
+            event_loop,
+            window,
+            mut graphics,
+            mut world,
+            mut dispatcher,
+            mut render_dispatcher,
+            mut renderers,
+            mut hooks,
+            mut timestep,
+            clear_color,
+        } = self;
+
+        let mut exit = false;
+        call(&mut hooks.on_start, &mut Context {
+            world: &mut world,
+            graphics: &mut graphics,
+            window: &window,
+            exit: &mut exit,
+        });
+        world.maintain();
+
+        let mut time = Instant::now();
+
+        event_loop.run(move |event, _, control_flow| {
+            *control_flow = if cfg!(feature = "metal-auto-capture") {
+                ControlFlow::Exit
+            } else {
+                ControlFlow::Poll
+            };
+
+            match event {
+                Event::WindowEvent { event: WindowEvent::Resized(size), .. } => {
+                    graphics.resize(size);
+                    fit_view(&mut world, &graphics);
+                    call(&mut hooks.on_resize, &mut Context {
+                        world: &mut world,
+                        graphics: &mut graphics,
+                        window: &window,
+                        exit: &mut exit,
+                    });
+                }
+                Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
+                    exit = true;
+                }
+                Event::WindowEvent { event, .. } => {
+                    input::handle_window_event(&world, &event);
+                }
+                Event::DeviceEvent { event, .. } => {
+                    input::handle_device_event(&world, &event);
+                }
+                Event::EventsCleared => {
+                    let elapsed = time.elapsed();
+                    time = Instant::now();
+
+                    for step in timestep.advance(elapsed) {
+                        world.write_resource::<Time>().advance(step);
+                        update_bounds(&mut world);
+                        dispatcher.dispatch(&world);
+                        world.maintain();
+                        call(&mut hooks.on_fixed_update, &mut Context {
+                            world: &mut world,
+                            graphics: &mut graphics,
+                            window: &window,
+                            exit: &mut exit,
+                        });
+                        world.write_resource::<InputState>().end_tick();
+                    }
+
+                    world.write_resource::<InterpolationAlpha>().0 = timestep.alpha();
+                    world.write_resource::<FrameTime>().advance(elapsed);
+                    render_dispatcher.dispatch(&world);
+
+                    call(&mut hooks.on_render, &mut Context {
+                        world: &mut world,
+                        graphics: &mut graphics,
+                        window: &window,
+                        exit: &mut exit,
+                    });
+                    world.write_resource::<InputState>().end_frame();
+                    for renderer in renderers.iter_mut() {
+                        if cfg!(feature = "hot-reload") {
+                            renderer.reload_shaders(&mut graphics);
+                        }
+                        renderer.prepare(&mut graphics, &world);
+                    }
+
+                    let mut frame = graphics.begin_frame(clear_color);
+                    {
+                        let mut rpass = frame.pass();
+                        for renderer in renderers.iter() {
+                            renderer.draw(&mut rpass);
+                        }
+                    }
+                    frame.end();
+                }
+                _ => (),
+            }
+
+            if exit {
+                *control_flow = ControlFlow::Exit;
+            }
+        })
+    }
+}
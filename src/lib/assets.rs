@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Where `Assets` looks for files unless told otherwise.
+pub const ASSET_DIR: &str = "assets";
+
+/// Something that can be loaded from a file by `Assets`.
+pub trait Asset: Sized + Send + Sync + 'static {
+    /// Decodes the contents of the file at `path`.
+    fn from_bytes(bytes: &[u8], path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// An image, decoded to rgba. PNG and JPEG files are supported.
+#[derive(Debug)]
+pub struct Texture {
+    image: image::RgbaImage,
+}
+
+impl Texture {
+    pub fn new(image: image::RgbaImage) -> Self {
+        Self { image }
+    }
+
+    pub fn image(&self) -> &image::RgbaImage {
+        &self.image
+    }
+}
+
+impl Asset for Texture {
+    fn from_bytes(bytes: &[u8], _path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self::new(image::load_from_memory(bytes)?.to_rgba()))
+    }
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    Io { path: PathBuf, error: std::io::Error },
+    Decode { path: PathBuf, error: Box<dyn std::error::Error + Send + Sync> },
+}
+
+impl std::fmt::Display for AssetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AssetError::Io { path, error } => write!(f, "unable to read {}: {}", path.display(), error),
+            AssetError::Decode { path, error } => write!(f, "unable to decode {}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HandleId(u64);
+
+/// A reference to an asset in `Assets<T>`. The asset stays loaded as long
+/// as any handle to it exists.
+pub struct Handle<T> {
+    id: HandleId,
+    count: Arc<()>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> HandleId {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            count: self.count.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Handle({})", self.id.0)
+    }
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    asset: T,
+    count: Arc<()>,
+    path: Option<PathBuf>,
+}
+
+/// Loads and caches assets of one type. Put it in the world as a resource,
+/// for example `Assets<Texture>`.
+#[derive(Debug)]
+pub struct Assets<T> {
+    root: PathBuf,
+    entries: HashMap<HandleId, Entry<T>>,
+    paths: HashMap<PathBuf, HandleId>,
+    next_id: u64,
+    version: u64,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self::new(ASSET_DIR)
+    }
+}
+
+impl<T> Assets<T> {
+    /// Loads assets from files relative to `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            entries: HashMap::new(),
+            paths: HashMap::new(),
+            next_id: 0,
+            version: 0,
+        }
+    }
+
+    /// Adds an asset that didn't come from a file.
+    pub fn add(&mut self, asset: T) -> Handle<T> {
+        self.insert(asset, None)
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.entries.get(&handle.id).map(|entry| &entry.asset)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (HandleId, &T)> {
+        self.entries.iter().map(|(&id, entry)| (id, &entry.asset))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Changes whenever an asset is added or removed.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Unloads every asset that has no handles left, returning how many
+    /// were unloaded.
+    pub fn collect_unused(&mut self) -> usize {
+        let unused: Vec<HandleId> = self.entries.iter()
+            .filter(|(_, entry)| Arc::strong_count(&entry.count) == 1)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &unused {
+            if let Some(path) = self.entries.remove(id).and_then(|entry| entry.path) {
+                self.paths.remove(&path);
+            }
+        }
+        if !unused.is_empty() {
+            self.version += 1;
+        }
+        unused.len()
+    }
+
+    fn insert(&mut self, asset: T, path: Option<PathBuf>) -> Handle<T> {
+        let id = HandleId(self.next_id);
+        self.next_id += 1;
+        self.version += 1;
+
+        let count = Arc::new(());
+        if let Some(path) = &path {
+            self.paths.insert(path.clone(), id);
+        }
+        self.entries.insert(id, Entry { asset, count: count.clone(), path });
+        Handle { id, count, marker: PhantomData }
+    }
+}
+
+impl<T: Asset> Assets<T> {
+    /// Loads the file at `path`, relative to the root. Files that are
+    /// already loaded aren't read again.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<Handle<T>, AssetError> {
+        let path = self.root.join(path);
+        if let Some(&id) = self.paths.get(&path) {
+            let count = self.entries[&id].count.clone();
+            return Ok(Handle { id, count, marker: PhantomData });
+        }
+
+        let bytes = std::fs::read(&path).map_err(|error| AssetError::Io { path: path.clone(), error })?;
+        let asset = T::from_bytes(&bytes, &path).map_err(|error| AssetError::Decode { path: path.clone(), error })?;
+        info!("Loaded {}", path.display());
+        Ok(self.insert(asset, Some(path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::util::TempDir;
+
+    /// A directory of its own for each test, holding a 2x1 png.
+    fn asset_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("assets-{}", name));
+        image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 255]))
+            .save(dir.path().join("red.png"))
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn loads_textures() {
+        let dir = asset_dir("load");
+        let mut textures = Assets::<Texture>::new(dir.path());
+        let handle = textures.load("red.png").unwrap();
+        let texture = textures.get(&handle).unwrap();
+        assert_eq!(texture.image().dimensions(), (2, 1));
+        assert_eq!(texture.image().get_pixel(1, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn loading_twice_uses_the_cache() {
+        let dir = asset_dir("cache");
+        let mut textures = Assets::<Texture>::new(dir.path());
+        let first = textures.load("red.png").unwrap();
+        std::fs::remove_file(dir.path().join("red.png")).unwrap();
+        let second = textures.load("red.png").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(textures.len(), 1);
+    }
+
+    #[test]
+    fn unused_assets_are_unloaded() {
+        let dir = asset_dir("unused");
+        let mut textures = Assets::<Texture>::new(dir.path());
+        let handle = textures.load("red.png").unwrap();
+        let copy = handle.clone();
+
+        drop(handle);
+        assert_eq!(textures.collect_unused(), 0);
+
+        let version = textures.version();
+        drop(copy);
+        assert_eq!(textures.collect_unused(), 1);
+        assert!(textures.is_empty());
+        assert_ne!(textures.version(), version);
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        let dir = asset_dir("missing");
+        let mut textures = Assets::<Texture>::new(dir.path());
+        match textures.load("blue.png") {
+            Err(AssetError::Io { .. }) => {}
+            other => panic!("expected an io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_files_are_decode_errors() {
+        let dir = asset_dir("decode");
+        std::fs::write(dir.path().join("bad.png"), b"not a png").unwrap();
+        let mut textures = Assets::<Texture>::new(dir.path());
+        match textures.load("bad.png") {
+            Err(AssetError::Decode { .. }) => {}
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+    }
+}
@@ -1,27 +1,637 @@
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix};
+
+/// How a `LookAtCamera` projects what it sees onto the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Things get smaller with distance, and nothing outside of `near` to
+    /// `far` is drawn.
+    Perspective { near: f32, far: f32 },
+    /// Things stay the same size with distance. `height` is how much of the
+    /// world is visible vertically, and the width follows the aspect ratio.
+    Orthographic { height: f32, near: f32, far: f32 },
+    /// A perspective projection without a far plane. Depth goes from 1.0 at
+    /// `near` to 0.0 at infinity, which spreads the depth buffer's precision
+    /// much more evenly. It needs a `DepthConfig::reverse_z` depth buffer.
+    InfiniteReverseZ { near: f32 },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective { near: 1.0, far: 10.0 }
+    }
+}
+
+/// A 3d camera at `position`, looking at `look_at`. `fovy` is the vertical
+/// field of view in degrees, which orthographic projections ignore. The
+/// `App` keeps the aspect ratio of a `LookAtCamera` resource matching the
+/// window, and the controllers in `controllers` move it around, assuming
+/// the default z up.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct LookAtCamera {
     pub aspect_ratio: f32,
     pub fovy: f32,
     pub position: cgmath::Point3<f32>,
     pub look_at: cgmath::Point3<f32>,
+    pub up: cgmath::Vector3<f32>,
+    pub projection: Projection,
 }
 
 impl LookAtCamera {
     pub fn new(aspect_ratio: f32, fovy: f32, position: cgmath::Point3<f32>, look_at: cgmath::Point3<f32>) -> Self {
-        Self { aspect_ratio, fovy, position, look_at }
+        Self {
+            aspect_ratio,
+            fovy,
+            position,
+            look_at,
+            up: cgmath::Vector3::unit_z(),
+            projection: Projection::default(),
+        }
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_up(mut self, up: cgmath::Vector3<f32>) -> Self {
+        self.up = up;
+        self
+    }
+
+    /// Transforms world coordinates into view space, where the camera is at
+    /// the origin looking down -z.
+    pub fn view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at(self.position, self.look_at, self.up)
+    }
+
+    /// Transforms view space into wgpu's clip space.
+    pub fn projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective { near, far } => {
+                let projection = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect_ratio, near, far);
+                super::util::OPENGL_TO_WGPU_MATRIX * projection
+            }
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect_ratio;
+                let projection = cgmath::ortho(-half_width, half_width, -half_height, half_height, near, far);
+                super::util::OPENGL_TO_WGPU_MATRIX * projection
+            }
+            Projection::InfiniteReverseZ { near } => {
+                let f = 1.0 / (cgmath::Rad::from(cgmath::Deg(self.fovy)).0 / 2.0).tan();
+                // Already in wgpu's depth range, and flipped on y like
+                // OPENGL_TO_WGPU_MATRIX
+                #[rustfmt::skip]
+                let projection = cgmath::Matrix4::new(
+                    f / self.aspect_ratio, 0.0, 0.0, 0.0,
+                    0.0, -f, 0.0, 0.0,
+                    0.0, 0.0, 0.0, -1.0,
+                    0.0, 0.0, near, 0.0,
+                );
+                projection
+            }
+        }
     }
 
+    /// Transforms world coordinates into clip space.
     pub fn generate_matrix(&self) -> cgmath::Matrix4<f32> {
-        let projection = cgmath::perspective(
-            cgmath::Deg(self.fovy), 
-            self.aspect_ratio, 
-            1.0, 
-            10.0
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// What the camera sees, in world space.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.generate_matrix())
+    }
+
+    /// The ray through a point on the screen, in view space. The point goes
+    /// from -1 to 1 across the screen, with y pointing up. Perspective rays
+    /// start at the camera, and orthographic ones on its plane.
+    pub fn view_ray(&self, point: cgmath::Vector2<f32>) -> Ray {
+        match self.projection {
+            Projection::Perspective { .. } | Projection::InfiniteReverseZ { .. } => {
+                let half_height = (cgmath::Rad::from(cgmath::Deg(self.fovy)).0 / 2.0).tan();
+                let half_width = half_height * self.aspect_ratio;
+                Ray {
+                    origin: cgmath::Point3::origin(),
+                    direction: cgmath::Vector3::new(point.x * half_width, point.y * half_height, -1.0).normalize(),
+                }
+            }
+            Projection::Orthographic { height, .. } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect_ratio;
+                Ray {
+                    origin: cgmath::Point3::new(point.x * half_width, point.y * half_height, 0.0),
+                    direction: -cgmath::Vector3::unit_z(),
+                }
+            }
+        }
+    }
+
+    /// Like `view_ray`, but in world space, for picking things under the
+    /// cursor.
+    pub fn world_ray(&self, point: cgmath::Vector2<f32>) -> Ray {
+        let ray = self.view_ray(point);
+        let view_to_world = self.view_matrix().invert().unwrap_or_else(cgmath::Matrix4::identity);
+        Ray {
+            origin: cgmath::Point3::from_homogeneous(view_to_world * ray.origin.to_homogeneous()),
+            direction: (view_to_world * ray.direction.extend(0.0)).truncate().normalize(),
+        }
+    }
+}
+
+/// A half line, for picking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: cgmath::Point3<f32>,
+    /// Always normalized.
+    pub direction: cgmath::Vector3<f32>,
+}
+
+impl Ray {
+    /// The point `distance` along the ray.
+    pub fn at(&self, distance: f32) -> cgmath::Point3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+/// Everything with a positive distance is in front of the plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: cgmath::Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_row(row: cgmath::Vector4<f32>) -> Self {
+        let normal = row.truncate();
+        let length = normal.magnitude();
+        // An infinite far plane has no normal, and everything is in front
+        // of it
+        if length < 1e-6 {
+            return Plane { normal, distance: row.w };
+        }
+        Plane {
+            normal: normal / length,
+            distance: row.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: cgmath::Point3<f32>) -> f32 {
+        self.normal.dot(point.to_vec()) + self.distance
+    }
+}
+
+/// The volume a camera sees, as six planes facing inwards, for culling
+/// things that can't be seen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of wgpu's clip volume from a view projection
+    /// matrix, so a point is inside when -w <= x <= w, -w <= y <= w and
+    /// 0 <= z <= w after transforming it.
+    pub fn from_matrix(matrix: cgmath::Matrix4<f32>) -> Self {
+        let row = |i| cgmath::Vector4::new(matrix.x[i], matrix.y[i], matrix.z[i], matrix.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                Plane::from_row(w + x),
+                Plane::from_row(w - x),
+                Plane::from_row(w + y),
+                Plane::from_row(w - y),
+                Plane::from_row(z),
+                Plane::from_row(w - z),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: cgmath::Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    /// Whether any of the sphere might be visible.
+    pub fn intersects_sphere(&self, center: cgmath::Point3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(center) >= -radius)
+    }
+
+    /// Whether any of the axis aligned box from `min` to `max` might be
+    /// visible. Boxes near the corners of the frustum can pass without
+    /// being visible, which is fine for culling.
+    pub fn intersects_aabb(&self, min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal
+            let corner = cgmath::Point3::new(
+                if plane.normal.x >= 0.0 { max.x } else { min.x },
+                if plane.normal.y >= 0.0 { max.y } else { min.y },
+                if plane.normal.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+}
+
+/// How a `Camera2d` fits its design size to a screen with a different
+/// aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalePolicy {
+    /// Shows all of the design size, and more of the world along the
+    /// longer side of the screen.
+    Fit,
+    /// Fills the screen with the design size, cutting off the edges along
+    /// the longer side of the screen.
+    Fill,
+    /// Shows the design height, with the width following the screen.
+    FixedHeight,
+    /// Shows as much of the world as fits when each unit of the design size
+    /// is drawn as a whole number of pixels, so pixel art stays sharp.
+    PixelPerfect,
+}
+
+/// The area that things should stay inside of. The `App` fits it to what the
+/// `Camera2d` sees when it isn't rotated before every fixed update, so it
+/// follows the camera as it moves and zooms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: cgmath::Vector2<f32>,
+    pub max: cgmath::Vector2<f32>,
+}
+
+impl Bounds {
+    pub fn size(&self) -> cgmath::Vector2<f32> {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: cgmath::Vector2<f32>) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x && point.y >= self.min.y && point.y <= self.max.y
+    }
+}
+
+/// An orthographic camera for 2d scenes, kept as a resource in the `World`.
+/// At the default zoom the view shows the design size, with the camera's
+/// position in the middle, and the scale policy decides what to do when the
+/// screen has a different aspect ratio. Rotation is counterclockwise in
+/// radians, and turns the view rather than the world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera2d {
+    pub position: cgmath::Vector2<f32>,
+    pub zoom: f32,
+    pub rotation: f32,
+    /// The size of the world that should be visible, in world units. For
+    /// `PixelPerfect` this is the size of the world in pixels at a scale of
+    /// 1.
+    pub design_size: cgmath::Vector2<f32>,
+    pub scale_policy: ScalePolicy,
+    /// The size of the screen in physical pixels. The `App` keeps this up to
+    /// date when the window is resized.
+    pub viewport: cgmath::Vector2<f32>,
+}
+
+impl Default for Camera2d {
+    /// A camera that sees from -1 to 1 on the shorter side of the screen.
+    fn default() -> Self {
+        Self {
+            position: cgmath::Vector2::new(0.0, 0.0),
+            zoom: 1.0,
+            rotation: 0.0,
+            design_size: cgmath::Vector2::new(2.0, 2.0),
+            scale_policy: ScalePolicy::Fit,
+            viewport: cgmath::Vector2::new(1.0, 1.0),
+        }
+    }
+}
+
+impl Camera2d {
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            viewport: cgmath::Vector2::new(width, height),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_design_size(mut self, width: f32, height: f32) -> Self {
+        self.design_size = cgmath::Vector2::new(width, height);
+        self
+    }
+
+    pub fn with_scale_policy(mut self, scale_policy: ScalePolicy) -> Self {
+        self.scale_policy = scale_policy;
+        self
+    }
+
+    pub fn set_viewport(&mut self, width: f32, height: f32) {
+        self.viewport = cgmath::Vector2::new(width, height);
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.viewport.x / self.viewport.y
+    }
+
+    /// How much of the world is visible, in world units.
+    pub fn view_size(&self) -> cgmath::Vector2<f32> {
+        let design = self.design_size;
+        // A minimized window has no size, so there's nothing to fit to
+        if self.viewport.x <= 0.0 || self.viewport.y <= 0.0 {
+            return design / self.zoom;
+        }
+
+        let aspect_ratio = self.aspect_ratio();
+        let wider = aspect_ratio > design.x / design.y;
+        let size = match self.scale_policy {
+            ScalePolicy::Fit if wider => cgmath::Vector2::new(design.y * aspect_ratio, design.y),
+            ScalePolicy::Fit => cgmath::Vector2::new(design.x, design.x / aspect_ratio),
+            ScalePolicy::Fill if wider => cgmath::Vector2::new(design.x, design.x / aspect_ratio),
+            ScalePolicy::Fill => cgmath::Vector2::new(design.y * aspect_ratio, design.y),
+            ScalePolicy::FixedHeight => cgmath::Vector2::new(design.y * aspect_ratio, design.y),
+            ScalePolicy::PixelPerfect => {
+                let scale = (self.viewport.x / design.x).min(self.viewport.y / design.y).floor().max(1.0);
+                self.viewport / scale
+            }
+        };
+        size / self.zoom
+    }
+
+    /// The part of the world the camera sees, ignoring rotation.
+    pub fn bounds(&self) -> Bounds {
+        let half_size = self.view_size() / 2.0;
+        Bounds {
+            min: self.position - half_size,
+            max: self.position + half_size,
+        }
+    }
+
+    /// Transforms world coordinates into clip space.
+    pub fn view_projection(&self) -> cgmath::Matrix4<f32> {
+        let size = self.view_size();
+        let scale = cgmath::Matrix4::from_nonuniform_scale(2.0 / size.x, 2.0 / size.y, 1.0);
+        let rotation = cgmath::Matrix4::from_angle_z(cgmath::Rad(-self.rotation));
+        let translation = cgmath::Matrix4::from_translation(-self.position.extend(0.0));
+        scale * rotation * translation
+    }
+
+    /// The world position under a point on the screen, like the mouse
+    /// cursor. Screen coordinates are in physical pixels from the top left
+    /// corner, like `InputState::cursor_position`.
+    pub fn screen_to_world(&self, screen: cgmath::Vector2<f32>) -> cgmath::Vector2<f32> {
+        let half_size = self.view_size() / 2.0;
+        let view = cgmath::Vector2::new(
+            (screen.x / self.viewport.x * 2.0 - 1.0) * half_size.x,
+            (1.0 - screen.y / self.viewport.y * 2.0) * half_size.y,
         );
-        let view = cgmath::Matrix4::look_at(
-            self.position, 
-            self.look_at, 
-            cgmath::Vector3::unit_z(),
+        let (sin, cos) = self.rotation.sin_cos();
+        self.position + cgmath::Vector2::new(view.x * cos - view.y * sin, view.x * sin + view.y * cos)
+    }
+
+    /// Where a world position ends up on the screen, in physical pixels from
+    /// the top left corner.
+    pub fn world_to_screen(&self, world: cgmath::Vector2<f32>) -> cgmath::Vector2<f32> {
+        let clip = self.view_projection() * world.extend(0.0).extend(1.0);
+        cgmath::Vector2::new(
+            (clip.x + 1.0) / 2.0 * self.viewport.x,
+            (1.0 - clip.y) / 2.0 * self.viewport.y,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: cgmath::Vector2<f32>, b: cgmath::Vector2<f32>) {
+        assert!((a - b).x.abs() < 1e-3 && (a - b).y.abs() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn default_camera_is_clip_space() {
+        assert_eq!(Camera2d::default().view_projection(), cgmath::Matrix4::identity());
+    }
+
+    #[test]
+    fn screen_corners_are_view_edges() {
+        let camera = Camera2d {
+            position: (3.0, -1.0).into(),
+            zoom: 0.5,
+            ..Camera2d::new(800.0, 800.0)
+        };
+        assert_close(camera.screen_to_world((0.0, 0.0).into()), (1.0, 1.0).into());
+        assert_close(camera.screen_to_world((400.0, 400.0).into()), (3.0, -1.0).into());
+        assert_close(camera.screen_to_world((800.0, 800.0).into()), (5.0, -3.0).into());
+    }
+
+    #[test]
+    fn rotation_turns_the_view() {
+        let camera = Camera2d {
+            rotation: std::f32::consts::FRAC_PI_2,
+            ..Camera2d::new(100.0, 100.0)
+        };
+        // With the camera turned a quarter left, world up points right on
+        // the screen
+        assert_close(camera.world_to_screen((0.0, 1.0).into()), (100.0, 50.0).into());
+        assert_close(camera.screen_to_world((0.0, 50.0).into()), (0.0, -1.0).into());
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let camera = Camera2d {
+            position: (0.25, 2.0).into(),
+            zoom: 3.0,
+            rotation: 0.7,
+            ..Camera2d::new(640.0, 480.0)
+        };
+        for &screen in &[(0.0, 0.0), (640.0, 480.0), (123.0, 456.0)] {
+            let screen = screen.into();
+            assert_close(camera.world_to_screen(camera.screen_to_world(screen)), screen);
+        }
+    }
+
+    #[test]
+    fn fit_shows_more_along_the_longer_side() {
+        let wide = Camera2d::new(800.0, 400.0);
+        assert_close(wide.view_size(), (4.0, 2.0).into());
+        let tall = Camera2d::new(400.0, 800.0);
+        assert_close(tall.view_size(), (2.0, 4.0).into());
+    }
+
+    #[test]
+    fn fill_cuts_off_the_longer_side() {
+        let wide = Camera2d::new(800.0, 400.0).with_scale_policy(ScalePolicy::Fill);
+        assert_close(wide.view_size(), (2.0, 1.0).into());
+        let tall = Camera2d::new(400.0, 800.0).with_scale_policy(ScalePolicy::Fill);
+        assert_close(tall.view_size(), (1.0, 2.0).into());
+    }
+
+    #[test]
+    fn fixed_height_keeps_the_height() {
+        let tall = Camera2d::new(400.0, 800.0).with_scale_policy(ScalePolicy::FixedHeight);
+        assert_close(tall.view_size(), (1.0, 2.0).into());
+    }
+
+    #[test]
+    fn pixel_perfect_scales_by_whole_pixels() {
+        let camera = Camera2d::new(700.0, 500.0)
+            .with_design_size(320.0, 240.0)
+            .with_scale_policy(ScalePolicy::PixelPerfect);
+        assert_close(camera.view_size(), (350.0, 250.0).into());
+
+        let small = Camera2d { viewport: (200.0, 100.0).into(), ..camera };
+        assert_close(small.view_size(), (200.0, 100.0).into());
+    }
+
+    #[test]
+    fn squares_stay_square() {
+        let camera = Camera2d::new(800.0, 400.0);
+        let corner = camera.world_to_screen((1.0, 1.0).into()) - camera.world_to_screen((0.0, 0.0).into());
+        assert_close(corner, (200.0, -200.0).into());
+    }
+
+    #[test]
+    fn bounds_match_the_view() {
+        let camera = Camera2d {
+            position: (1.0, 0.0).into(),
+            zoom: 2.0,
+            ..Camera2d::new(800.0, 400.0)
+        };
+        let bounds = camera.bounds();
+        assert_close(bounds.min, (0.0, -0.5).into());
+        assert_close(bounds.max, (2.0, 0.5).into());
+        assert_close(camera.screen_to_world((0.0, 400.0).into()), bounds.min);
+    }
+
+    #[test]
+    fn minimized_windows_keep_the_design_size() {
+        let camera = Camera2d::new(0.0, 0.0);
+        assert_close(camera.view_size(), (2.0, 2.0).into());
+    }
+
+    fn assert_close3(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    /// Where a world position ends up in clip space, after the divide.
+    fn project(camera: &LookAtCamera, point: cgmath::Point3<f32>) -> cgmath::Vector3<f32> {
+        let clip = camera.generate_matrix() * point.to_homogeneous();
+        clip.truncate() / clip.w
+    }
+
+    fn looking_down_y() -> LookAtCamera {
+        LookAtCamera::new(2.0, 90.0, (0.0, 0.0, 0.0).into(), (0.0, 1.0, 0.0).into())
+    }
+
+    #[test]
+    fn default_projection_is_unchanged() {
+        let camera = LookAtCamera::new(1.5, 45.0, (1.5, -5.0, 3.0).into(), (0.0, 0.0, 0.0).into());
+        let expected = super::super::util::OPENGL_TO_WGPU_MATRIX
+            * cgmath::perspective(cgmath::Deg(45.0), 1.5, 1.0, 10.0)
+            * cgmath::Matrix4::look_at(camera.position, camera.look_at, cgmath::Vector3::unit_z());
+        assert_eq!(camera.generate_matrix(), expected);
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_to_the_depth_range() {
+        let camera = looking_down_y().with_projection(Projection::Perspective { near: 0.5, far: 50.0 });
+        assert_close3(project(&camera, (0.0, 0.5, 0.0).into()), (0.0, 0.0, 0.0).into());
+        assert_close3(project(&camera, (0.0, 50.0, 0.0).into()), (0.0, 0.0, 1.0).into());
+        // A 90 degree field of view sees as far up as it does forward, and
+        // twice as far sideways; clip space is flipped on y for wgpu
+        assert_close3(project(&camera, (-4.0, 2.0, 2.0).into()).truncate().extend(0.0), (-1.0, -1.0, 0.0).into());
+    }
+
+    #[test]
+    fn orthographic_keeps_sizes() {
+        let camera = looking_down_y().with_projection(Projection::Orthographic { height: 4.0, near: 1.0, far: 11.0 });
+        assert_close3(project(&camera, (4.0, 1.0, 2.0).into()), (1.0, -1.0, 0.0).into());
+        assert_close3(project(&camera, (4.0, 11.0, 2.0).into()), (1.0, -1.0, 1.0).into());
+        assert_close3(project(&camera, (-2.0, 6.0, 0.0).into()), (-0.5, 0.0, 0.5).into());
+    }
+
+    #[test]
+    fn infinite_reverse_z_puts_near_at_one() {
+        let camera = looking_down_y().with_projection(Projection::InfiniteReverseZ { near: 0.1 });
+        assert_close3(project(&camera, (0.0, 0.1, 0.0).into()), (0.0, 0.0, 1.0).into());
+        assert_close3(project(&camera, (0.0, 1.0, 0.0).into()), (0.0, 0.0, 0.1).into());
+        let far = project(&camera, (0.0, 1.0e6, 0.0).into());
+        assert!(far.z > 0.0 && far.z < 1.0e-6);
+        // Same x and y as a regular perspective projection
+        let perspective = looking_down_y();
+        assert_close3(
+            project(&camera, (-4.0, 2.0, 2.0).into()).truncate().extend(0.0),
+            project(&perspective, (-4.0, 2.0, 2.0).into()).truncate().extend(0.0),
         );
-        super::util::OPENGL_TO_WGPU_MATRIX * projection * view
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn up_vector_turns_the_view() {
+        let camera = LookAtCamera::new(1.0, 90.0, (0.0, 0.0, 5.0).into(), (0.0, 0.0, 0.0).into())
+            .with_up(cgmath::Vector3::unit_y());
+        let up = project(&camera, (0.0, 1.0, 0.0).into());
+        assert!(up.y < 0.0 && up.x.abs() < 1e-6);
+        let right = project(&camera, (1.0, 0.0, 0.0).into());
+        assert!(right.x > 0.0 && right.y.abs() < 1e-6);
+    }
+
+    #[test]
+    fn clip_volume_frustum() {
+        let frustum = Frustum::from_matrix(cgmath::Matrix4::identity());
+        assert!(frustum.contains_point((0.0, 0.0, 0.5).into()));
+        assert!(frustum.contains_point((1.0, -1.0, 0.0).into()));
+        assert!(!frustum.contains_point((0.0, 0.0, -0.1).into()));
+        assert!(!frustum.contains_point((0.0, 1.1, 0.5).into()));
+        assert_close3(frustum.planes[0].normal, (1.0, 0.0, 0.0).into());
+        assert!((frustum.planes[0].signed_distance((0.5, 0.0, 0.0).into()) - 1.5).abs() < 1e-6);
+
+        assert!(frustum.intersects_sphere((1.5, 0.0, 0.5).into(), 0.6));
+        assert!(!frustum.intersects_sphere((1.5, 0.0, 0.5).into(), 0.4));
+        assert!(frustum.intersects_aabb((1.0, -3.0, -3.0).into(), (2.0, 3.0, 3.0).into()));
+        assert!(!frustum.intersects_aabb((1.1, -3.0, -3.0).into(), (2.0, 3.0, 3.0).into()));
+    }
+
+    #[test]
+    fn camera_frustum() {
+        let camera = looking_down_y().with_projection(Projection::Perspective { near: 1.0, far: 10.0 });
+        let frustum = camera.frustum();
+        assert!(frustum.contains_point((0.0, 5.0, 0.0).into()));
+        assert!(frustum.contains_point((-9.9, 5.0, 4.9).into()));
+        assert!(!frustum.contains_point((0.0, -5.0, 0.0).into()));
+        assert!(!frustum.contains_point((0.0, 0.5, 0.0).into()));
+        assert!(!frustum.contains_point((0.0, 11.0, 0.0).into()));
+        assert!(!frustum.contains_point((0.0, 5.0, 5.1).into()));
+
+        let infinite = camera.with_projection(Projection::InfiniteReverseZ { near: 1.0 }).frustum();
+        assert!(infinite.contains_point((0.0, 1.0e6, 0.0).into()));
+        assert!(!infinite.contains_point((0.0, 0.5, 0.0).into()));
+        assert!(infinite.intersects_sphere((0.0, 1.0e6, 0.0).into(), 1.0));
+    }
+
+    #[test]
+    fn view_rays() {
+        let camera = looking_down_y();
+        let center = camera.view_ray((0.0, 0.0).into());
+        assert_close3(center.direction, (0.0, 0.0, -1.0).into());
+
+        // The top right corner of a 90 degree view twice as wide as it is tall
+        let corner = camera.view_ray((1.0, 1.0).into());
+        assert_close3(corner.direction, cgmath::Vector3::new(2.0, 1.0, -1.0).normalize());
+
+        let ortho = camera.with_projection(Projection::Orthographic { height: 4.0, near: 1.0, far: 10.0 });
+        let ray = ortho.view_ray((1.0, -0.5).into());
+        assert_close3(ray.origin.to_vec(), (4.0, -1.0, 0.0).into());
+        assert_close3(ray.direction, (0.0, 0.0, -1.0).into());
+    }
+
+    #[test]
+    fn world_rays_hit_what_is_on_screen() {
+        let camera = LookAtCamera::new(1.5, 60.0, (1.5, -5.0, 3.0).into(), (0.0, 0.0, 0.0).into());
+        let center = camera.world_ray((0.0, 0.0).into());
+        let distance = (camera.look_at - camera.position).magnitude();
+        assert_close3(center.at(distance).to_vec(), (0.0, 0.0, 0.0).into());
+
+        let ray = camera.world_ray((0.5, 0.25).into());
+        assert_close3(ray.origin.to_vec(), camera.position.to_vec());
+        assert_close3(project(&camera, ray.at(5.0)).truncate().extend(0.0), (0.5, -0.25, 0.0).into());
+
+        let ortho = camera.with_projection(Projection::Orthographic { height: 3.0, near: 0.1, far: 20.0 });
+        let ray = ortho.world_ray((-0.5, 0.75).into());
+        assert_close3(project(&ortho, ray.at(5.0)).truncate().extend(0.0), (-0.5, -0.75, 0.0).into());
+    }
+}
@@ -0,0 +1,112 @@
+use specs::prelude::*;
+
+use crate::lib::{
+    assets::{Handle, Texture},
+    graphics::SpriteId,
+    mesh::Mesh,
+};
+
+#[derive(Debug, Clone, Copy, Component)]
+#[storage(VecStorage)]
+pub struct Position(pub cgmath::Vector2<f32>);
+
+#[derive(Debug, Clone, Component)]
+#[storage(VecStorage)]
+pub struct Appearance {
+    pub color: cgmath::Vector3<f32>,
+    pub origin: cgmath::Vector2<f32>,
+    pub scale: cgmath::Vector2<f32>,
+    pub rotation: f32,
+    /// A named sprite in the `Atlas` resource to draw. This takes priority
+    /// over `texture`.
+    pub sprite: Option<SpriteId>,
+    /// Drawn from the `Atlas` resource once it's been packed. Without a
+    /// sprite or a texture, the quad is drawn in its plain color.
+    pub texture: Option<Handle<Texture>>,
+}
+
+/// Where an entity is in 3d. It's scaled first, then rotated, then moved.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+#[storage(VecStorage)]
+pub struct Transform {
+    pub translation: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: cgmath::Vector3::new(0.0, 0.0, 0.0),
+            rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: cgmath::Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: cgmath::Quaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: cgmath::Vector3<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// The model matrix, taking points from the entity's space to the world.
+    pub fn matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.translation)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// Makes an entity's `Transform` relative to another entity's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+#[storage(DenseVecStorage)]
+pub struct Parent(pub Entity);
+
+/// Where an entity ends up in the world, after its parents' transforms.
+/// The `TransformSystem` keeps this up to date for every entity with a
+/// `Transform`.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+#[storage(VecStorage)]
+pub struct GlobalTransform(pub cgmath::Matrix4<f32>);
+
+/// Drawn by the `MeshRenderer`, wherever the entity's `GlobalTransform`, or
+/// `Transform` without one, puts it.
+#[derive(Debug, Clone, Component)]
+#[storage(VecStorage)]
+pub struct MeshHandle(pub Handle<Mesh>);
+
+/// The position and rotation an entity had before the last fixed update.
+/// The `App` keeps this up to date for every entity with a `Position` and
+/// an `Appearance`.
+#[derive(Debug, Clone, Copy, Component)]
+#[storage(VecStorage)]
+pub struct Previous {
+    pub position: cgmath::Vector2<f32>,
+    pub rotation: f32,
+}
+
+/// How many instances the `InstanceUpdateSystem` kept and culled in the
+/// last frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CullStats {
+    pub visible: usize,
+    pub culled: usize,
+}
+
+/// How far the current frame is between the last fixed update and the next
+/// one, from 0.0 to 1.0.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InterpolationAlpha(pub f32);
@@ -0,0 +1,391 @@
+use cgmath::{InnerSpace, Zero};
+use specs::prelude::*;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::lib::{camera::LookAtCamera, input::InputState, time::FrameTime};
+
+/// Looking straight up or down would leave the camera without a sensible
+/// up vector, so pitch always stays a little short of that.
+const MAX_LOOK_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// Points the camera's way, from its yaw around the z axis and its pitch
+/// above the xy plane.
+fn direction(yaw: f32, pitch: f32) -> cgmath::Vector3<f32> {
+    let (sin_yaw, cos_yaw) = yaw.sin_cos();
+    let (sin_pitch, cos_pitch) = pitch.sin_cos();
+    cgmath::Vector3::new(cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch)
+}
+
+/// The yaw and pitch of `direction`, so that `direction(yaw, pitch)` points
+/// the same way.
+fn angles(direction: cgmath::Vector3<f32>) -> (f32, f32) {
+    let direction = direction.normalize();
+    (direction.y.atan2(direction.x), clamp_pitch(direction.z, 1.0).asin())
+}
+
+/// Keeps `pitch` between `-max_pitch` and `max_pitch`.
+fn clamp_pitch(pitch: f32, max_pitch: f32) -> f32 {
+    let pitch = pitch.min(max_pitch);
+    pitch.max(-max_pitch)
+}
+
+/// The pressed movement keys as x to the right, y forward and z up.
+fn movement_keys(input: &InputState) -> cgmath::Vector3<f32> {
+    let axis = |positive, negative| {
+        let mut value = 0.0;
+        if input.is_key_down(positive) {
+            value += 1.0;
+        }
+        if input.is_key_down(negative) {
+            value -= 1.0;
+        }
+        value
+    };
+    cgmath::Vector3::new(
+        axis(VirtualKeyCode::D, VirtualKeyCode::A),
+        axis(VirtualKeyCode::W, VirtualKeyCode::S),
+        axis(VirtualKeyCode::Space, VirtualKeyCode::LShift),
+    )
+}
+
+fn is_looking(input: &InputState, button: Option<MouseButton>) -> bool {
+    match button {
+        Some(button) => input.is_mouse_down(button),
+        None => true,
+    }
+}
+
+/// Circles the camera around a target while a mouse button is held, and
+/// zooms in and out with the scroll wheel.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct OrbitController {
+    pub target: cgmath::Point3<f32>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Radians per pixel the mouse moves.
+    pub sensitivity: f32,
+    /// What the distance gets multiplied by for each line scrolled up.
+    pub zoom_factor: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub drag_button: MouseButton,
+}
+
+impl OrbitController {
+    /// Orbits around where the camera is looking, from where it is now.
+    pub fn new(camera: &LookAtCamera) -> Self {
+        let offset = camera.position - camera.look_at;
+        let (yaw, pitch) = angles(offset);
+        Self {
+            target: camera.look_at,
+            distance: offset.magnitude(),
+            yaw,
+            pitch,
+            sensitivity: 0.005,
+            zoom_factor: 0.9,
+            min_distance: 0.1,
+            max_distance: 100.0,
+            drag_button: MouseButton::Left,
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut LookAtCamera, input: &InputState) {
+        if input.is_mouse_down(self.drag_button) {
+            let delta = input.frame_mouse_delta();
+            self.yaw -= delta.x * self.sensitivity;
+            self.pitch = clamp_pitch(self.pitch + delta.y * self.sensitivity, MAX_LOOK_PITCH);
+        }
+        let scroll = input.frame_scroll_delta().y;
+        self.distance = (self.distance * self.zoom_factor.powf(scroll))
+            .max(self.min_distance)
+            .min(self.max_distance);
+
+        camera.position = self.target + direction(self.yaw, self.pitch) * self.distance;
+        camera.look_at = self.target;
+    }
+}
+
+/// Flies the camera freely with WASD, rising with space and sinking with
+/// left shift, and looks around with the mouse.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct FlyController {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Units per second.
+    pub speed: f32,
+    /// Radians per pixel the mouse moves.
+    pub sensitivity: f32,
+    /// The mouse only looks around while this is held. Without a button it
+    /// always does, which works best with a grabbed cursor.
+    pub look_button: Option<MouseButton>,
+}
+
+impl FlyController {
+    /// Flies from where the camera is, looking where it's looking.
+    pub fn new(camera: &LookAtCamera) -> Self {
+        let (yaw, pitch) = angles(camera.look_at - camera.position);
+        Self {
+            yaw,
+            pitch: clamp_pitch(pitch, MAX_LOOK_PITCH),
+            speed: 5.0,
+            sensitivity: 0.003,
+            look_button: Some(MouseButton::Right),
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut LookAtCamera, input: &InputState, dt: f32) {
+        if is_looking(input, self.look_button) {
+            let delta = input.frame_mouse_delta();
+            self.yaw -= delta.x * self.sensitivity;
+            self.pitch = clamp_pitch(self.pitch - delta.y * self.sensitivity, MAX_LOOK_PITCH);
+        }
+
+        let forward = direction(self.yaw, self.pitch);
+        let right = cgmath::Vector3::new(self.yaw.sin(), -self.yaw.cos(), 0.0);
+        let keys = movement_keys(input);
+        let velocity = right * keys.x + forward * keys.y + cgmath::Vector3::unit_z() * keys.z;
+        if !velocity.is_zero() {
+            camera.position += velocity.normalize() * self.speed * dt;
+        }
+        camera.look_at = camera.position + forward;
+    }
+}
+
+/// Walks the camera around with WASD while looking around with the mouse.
+/// Unlike the `FlyController`, looking up or down doesn't change where the
+/// camera walks, and it can't look further up or down than `max_pitch`.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct FirstPersonController {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Units per second.
+    pub speed: f32,
+    /// Radians per pixel the mouse moves.
+    pub sensitivity: f32,
+    /// How far up or down the camera can look, in radians.
+    pub max_pitch: f32,
+    /// The mouse only looks around while this is held. Without a button it
+    /// always does, which works best with a grabbed cursor.
+    pub look_button: Option<MouseButton>,
+}
+
+impl FirstPersonController {
+    /// Walks from where the camera is, looking where it's looking.
+    pub fn new(camera: &LookAtCamera) -> Self {
+        let (yaw, pitch) = angles(camera.look_at - camera.position);
+        let max_pitch = 85.0 * std::f32::consts::PI / 180.0;
+        Self {
+            yaw,
+            pitch: clamp_pitch(pitch, max_pitch),
+            speed: 3.0,
+            sensitivity: 0.003,
+            max_pitch,
+            look_button: Some(MouseButton::Right),
+        }
+    }
+
+    pub fn with_max_pitch(mut self, max_pitch: f32) -> Self {
+        self.max_pitch = max_pitch.min(MAX_LOOK_PITCH);
+        self.pitch = clamp_pitch(self.pitch, self.max_pitch);
+        self
+    }
+
+    pub fn update(&mut self, camera: &mut LookAtCamera, input: &InputState, dt: f32) {
+        if is_looking(input, self.look_button) {
+            let delta = input.frame_mouse_delta();
+            let max_pitch = self.max_pitch.min(MAX_LOOK_PITCH);
+            self.yaw -= delta.x * self.sensitivity;
+            self.pitch = clamp_pitch(self.pitch - delta.y * self.sensitivity, max_pitch);
+        }
+
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let forward = cgmath::Vector3::new(cos_yaw, sin_yaw, 0.0);
+        let right = cgmath::Vector3::new(sin_yaw, -cos_yaw, 0.0);
+        let keys = movement_keys(input);
+        let velocity = right * keys.x + forward * keys.y;
+        if !velocity.is_zero() {
+            camera.position += velocity.normalize() * self.speed * dt;
+        }
+        camera.look_at = camera.position + direction(self.yaw, self.pitch);
+    }
+}
+
+/// Drives the `LookAtCamera` resource with whichever controllers are in the
+/// world. There's only one camera, so there should only be one controller
+/// at a time. The `App` runs this every frame so the camera moves smoothly,
+/// which is why it uses the frame's input and time.
+pub struct CameraControllerSystem;
+impl<'a> System<'a> for CameraControllerSystem {
+    type SystemData = (
+        Read<'a, InputState>,
+        Read<'a, FrameTime>,
+        Option<Write<'a, LookAtCamera>>,
+        WriteStorage<'a, OrbitController>,
+        WriteStorage<'a, FlyController>,
+        WriteStorage<'a, FirstPersonController>,
+    );
+
+    fn run(&mut self, (input, time, camera, mut w_orbit, mut w_fly, mut w_first_person): Self::SystemData) {
+        let mut camera = match camera {
+            Some(camera) => camera,
+            None => return,
+        };
+        let dt = time.delta_seconds();
+        for orbit in (&mut w_orbit).join() {
+            orbit.update(&mut camera, &input);
+        }
+        for fly in (&mut w_fly).join() {
+            fly.update(&mut camera, &input, dt);
+        }
+        for first_person in (&mut w_first_person).join() {
+            first_person.update(&mut camera, &input, dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: cgmath::Point3<f32>, b: cgmath::Point3<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn camera() -> LookAtCamera {
+        LookAtCamera::new(1.0, 45.0, (4.0, 0.0, 0.0).into(), (0.0, 0.0, 0.0).into())
+    }
+
+    #[test]
+    fn orbit_starts_where_the_camera_is() {
+        let mut camera = camera();
+        let mut orbit = OrbitController::new(&camera);
+        assert!((orbit.distance - 4.0).abs() < 1e-6);
+        orbit.update(&mut camera, &InputState::default());
+        assert_close(camera.position, (4.0, 0.0, 0.0).into());
+        assert_close(camera.look_at, (0.0, 0.0, 0.0).into());
+    }
+
+    #[test]
+    fn orbit_drags_and_zooms() {
+        let mut camera = camera();
+        let mut orbit = OrbitController::new(&camera);
+        orbit.sensitivity = std::f32::consts::FRAC_PI_2 / 100.0;
+        let mut input = InputState::default();
+
+        // Moving the mouse without the button does nothing
+        input.move_mouse((100.0, 0.0).into());
+        orbit.update(&mut camera, &input);
+        assert_close(camera.position, (4.0, 0.0, 0.0).into());
+
+        input.press_mouse(MouseButton::Left);
+        orbit.update(&mut camera, &input);
+        assert_close(camera.position, (0.0, -4.0, 0.0).into());
+
+        input.end_frame();
+        input.scroll((0.0, 1.0).into());
+        orbit.update(&mut camera, &input);
+        assert_close(camera.position, (0.0, -3.6, 0.0).into());
+    }
+
+    #[test]
+    fn orbit_clamps_pitch_and_distance() {
+        let mut camera = camera();
+        let mut orbit = OrbitController::new(&camera);
+        let mut input = InputState::default();
+        input.press_mouse(MouseButton::Left);
+        input.move_mouse((0.0, 100_000.0).into());
+        input.scroll((0.0, -1000.0).into());
+        orbit.update(&mut camera, &input);
+        assert!((orbit.pitch - MAX_LOOK_PITCH).abs() < 1e-6);
+        assert!((orbit.distance - orbit.max_distance).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fly_moves_where_it_looks() {
+        let mut camera = camera();
+        let mut fly = FlyController::new(&camera);
+        assert!((fly.yaw.abs() - std::f32::consts::PI).abs() < 1e-6);
+
+        let mut input = InputState::default();
+        input.press_key(VirtualKeyCode::W);
+        fly.update(&mut camera, &input, 0.5);
+        assert_close(camera.position, (1.5, 0.0, 0.0).into());
+        assert_close(camera.look_at, (0.5, 0.0, 0.0).into());
+
+        // Looking up makes forward go up too
+        fly.pitch = std::f32::consts::FRAC_PI_4;
+        fly.update(&mut camera, &input, 0.2);
+        let diagonal = 2.0_f32.sqrt() / 2.0;
+        assert_close(camera.position, (1.5 - diagonal, 0.0, diagonal).into());
+    }
+
+    #[test]
+    fn fly_strafes_and_rises() {
+        let mut camera = camera();
+        let mut fly = FlyController::new(&camera);
+        fly.speed = 1.0;
+        let mut input = InputState::default();
+        input.press_key(VirtualKeyCode::D);
+        fly.update(&mut camera, &input, 1.0);
+        // Facing -x with z up, right is +y
+        assert_close(camera.position, (4.0, 1.0, 0.0).into());
+
+        input.release_key(VirtualKeyCode::D);
+        input.press_key(VirtualKeyCode::Space);
+        fly.update(&mut camera, &input, 1.0);
+        assert_close(camera.position, (4.0, 1.0, 1.0).into());
+    }
+
+    #[test]
+    fn fly_looks_only_while_the_button_is_held() {
+        let mut camera = camera();
+        let mut fly = FlyController::new(&camera);
+        let mut input = InputState::default();
+        input.move_mouse((0.0, -100.0).into());
+        fly.update(&mut camera, &input, 0.0);
+        assert_eq!(fly.pitch, 0.0);
+
+        input.press_mouse(MouseButton::Right);
+        fly.update(&mut camera, &input, 0.0);
+        assert!(fly.pitch > 0.0);
+        assert!(camera.look_at.z > 0.0);
+    }
+
+    #[test]
+    fn first_person_clamps_pitch_and_walks_level() {
+        let mut camera = camera();
+        let mut first_person = FirstPersonController::new(&camera).with_max_pitch(0.5);
+        first_person.look_button = None;
+        first_person.speed = 1.0;
+
+        let mut input = InputState::default();
+        input.move_mouse((0.0, -100_000.0).into());
+        input.press_key(VirtualKeyCode::W);
+        first_person.update(&mut camera, &input, 1.0);
+        assert_eq!(first_person.pitch, 0.5);
+        // Looking up doesn't make it walk up
+        assert_close(camera.position, (3.0, 0.0, 0.0).into());
+        assert!(camera.look_at.z > 0.0);
+    }
+
+    #[test]
+    fn system_drives_the_camera_resource() {
+        let mut world = World::new();
+        world.register::<OrbitController>();
+        world.register::<FlyController>();
+        world.register::<FirstPersonController>();
+        world.insert(InputState::default());
+        world.insert(FrameTime::default());
+        world.insert(camera());
+
+        let mut orbit = OrbitController::new(&camera());
+        orbit.distance = 2.0;
+        world.create_entity().with(orbit).build();
+        CameraControllerSystem.run_now(&world);
+        assert_close(world.read_resource::<LookAtCamera>().position, (2.0, 0.0, 0.0).into());
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::lib::assets::{Assets, HandleId, Texture};
+
+/// Transparent pixels kept between sprites, so linear filtering doesn't
+/// bleed one sprite into the next.
+const PADDING: u32 = 1;
+
+static NEXT_ATLAS_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The part of a texture a sprite uses, in texture coordinates.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub offset: cgmath::Vector2<f32>,
+    pub size: cgmath::Vector2<f32>,
+}
+
+impl UvRect {
+    pub const FULL: UvRect = UvRect {
+        offset: cgmath::Vector2 { x: 0.0, y: 0.0 },
+        size: cgmath::Vector2 { x: 1.0, y: 1.0 },
+    };
+
+    /// Samples a single point of the texture, wherever it is on the quad.
+    pub fn point(uv: cgmath::Vector2<f32>) -> Self {
+        Self {
+            offset: uv,
+            size: cgmath::Vector2 { x: 0.0, y: 0.0 },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpriteId(usize);
+
+#[derive(Debug)]
+pub enum AtlasError {
+    /// The sprites don't fit in an atlas of the maximum size.
+    TooLarge { max_size: u32 },
+}
+
+impl std::fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AtlasError::TooLarge { max_size } => {
+                write!(f, "sprites don't fit in a {0}x{0} atlas", max_size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+/// Collects images to pack into an `Atlas`.
+pub struct AtlasBuilder {
+    images: Vec<image::RgbaImage>,
+    names: HashMap<String, SpriteId>,
+    textures: HashMap<HandleId, SpriteId>,
+    max_size: u32,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self {
+            images: Vec::new(),
+            names: HashMap::new(),
+            textures: HashMap::new(),
+            max_size: 4096,
+        }
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The largest width and height the atlas texture can have.
+    pub fn with_max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn add(&mut self, name: &str, image: image::RgbaImage) -> SpriteId {
+        let id = SpriteId(self.images.len());
+        self.images.push(image);
+        self.names.insert(name.to_string(), id);
+        id
+    }
+
+    /// Adds a texture asset, so it can be found with `Atlas::texture`.
+    pub fn add_texture(&mut self, texture: HandleId, image: image::RgbaImage) -> SpriteId {
+        let id = SpriteId(self.images.len());
+        self.images.push(image);
+        self.textures.insert(texture, id);
+        id
+    }
+
+    /// Packs the images into rows, tallest first, using the narrowest power
+    /// of two width that fits them. A white texel is packed with them, for
+    /// quads without a sprite.
+    pub fn build(self) -> Result<Atlas, AtlasError> {
+        let AtlasBuilder { mut images, names, textures, max_size } = self;
+        images.push(image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])));
+
+        // Nothing goes after the last image in a row, so it needs no padding
+        let widest = images.iter().map(|image| image.width()).max().unwrap_or(1);
+        let area: u32 = images.iter()
+            .map(|image| (image.width() + PADDING) * (image.height() + PADDING))
+            .sum();
+        // The padding in the estimate can't be allowed to push the width past
+        // the limit, when packing may still fit
+        let widest_allowed = if max_size.is_power_of_two() { max_size } else { max_size.next_power_of_two() / 2 };
+        let estimate = ((area as f32).sqrt() as u32).next_power_of_two().min(widest_allowed);
+        let mut width = widest.next_power_of_two().max(estimate);
+
+        let (positions, height) = loop {
+            if width > max_size {
+                return Err(AtlasError::TooLarge { max_size });
+            }
+            let (positions, height) = pack(&images, width);
+            if height <= max_size {
+                break (positions, height.max(1));
+            }
+            width *= 2;
+        };
+
+        let mut atlas_image = image::RgbaImage::new(width, height);
+        let mut rects = Vec::with_capacity(images.len());
+        for (image, &(x, y)) in images.iter().zip(positions.iter()) {
+            image::imageops::replace(&mut atlas_image, image, x, y);
+            rects.push(UvRect {
+                offset: (x as f32 / width as f32, y as f32 / height as f32).into(),
+                size: (image.width() as f32 / width as f32, image.height() as f32 / height as f32).into(),
+            });
+        }
+        // Sampling the middle of the white texel keeps filtering from
+        // reaching the padding around it
+        let white = rects.pop().unwrap();
+        let white = UvRect::point(white.offset + white.size / 2.0);
+
+        Ok(Atlas {
+            id: NEXT_ATLAS_ID.fetch_add(1, Ordering::Relaxed),
+            image: atlas_image,
+            rects,
+            white,
+            names,
+            textures,
+        })
+    }
+}
+
+/// Places the images in rows that are `width` pixels wide, returning the
+/// position of each image and the total height.
+fn pack(images: &[image::RgbaImage], width: u32) -> (Vec<(u32, u32)>, u32) {
+    let mut order: Vec<usize> = (0..images.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+
+    let mut positions = vec![(0, 0); images.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for i in order {
+        let (w, h) = images[i].dimensions();
+        if x > 0 && x + w > width {
+            x = 0;
+            y += row_height + PADDING;
+            row_height = 0;
+        }
+        positions[i] = (x, y);
+        x += w + PADDING;
+        row_height = row_height.max(h);
+    }
+    (positions, y + row_height)
+}
+
+/// Many images packed into one texture. Put it in the world as a resource
+/// and the `QuadRenderer` will draw sprites from it.
+#[derive(Debug)]
+pub struct Atlas {
+    id: u64,
+    image: image::RgbaImage,
+    rects: Vec<UvRect>,
+    white: UvRect,
+    names: HashMap<String, SpriteId>,
+    textures: HashMap<HandleId, SpriteId>,
+}
+
+impl Default for Atlas {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            image: image::RgbaImage::new(0, 0),
+            rects: Vec::new(),
+            // Renderers use a plain white texture until there's an atlas
+            white: UvRect::point((0.5, 0.5).into()),
+            names: HashMap::new(),
+            textures: HashMap::new(),
+        }
+    }
+}
+
+impl Atlas {
+    /// Packs every loaded texture.
+    pub fn from_textures(textures: &Assets<Texture>) -> Result<Self, AtlasError> {
+        let mut builder = AtlasBuilder::new();
+        for (id, texture) in textures.iter() {
+            builder.add_texture(id, texture.image().clone());
+        }
+        builder.build()
+    }
+
+    /// Identifies this particular atlas, so renderers know when to upload a
+    /// new one. The default, empty atlas has an id of 0.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn image(&self) -> &image::RgbaImage {
+        &self.image
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<SpriteId> {
+        self.names.get(name).cloned()
+    }
+
+    pub fn texture(&self, texture: HandleId) -> Option<SpriteId> {
+        self.textures.get(&texture).cloned()
+    }
+
+    /// Where a sprite is in the atlas. Unknown sprites are drawn white.
+    pub fn rect(&self, sprite: SpriteId) -> UvRect {
+        self.rects.get(sprite.0).cloned().unwrap_or(self.white)
+    }
+
+    /// A white texel, which leaves quads in their plain color.
+    pub fn white(&self) -> UvRect {
+        self.white
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba(pixel))
+    }
+
+    fn pixel_rect(atlas: &Atlas, sprite: SpriteId) -> (u32, u32, u32, u32) {
+        let rect = atlas.rect(sprite);
+        let (width, height) = atlas.image().dimensions();
+        (
+            (rect.offset.x * width as f32).round() as u32,
+            (rect.offset.y * height as f32).round() as u32,
+            (rect.size.x * width as f32).round() as u32,
+            (rect.size.y * height as f32).round() as u32,
+        )
+    }
+
+    fn overlaps(a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    #[test]
+    fn sprites_keep_their_pixels() {
+        let mut builder = AtlasBuilder::new();
+        let red = builder.add("red", solid(4, 8, [255, 0, 0, 255]));
+        let blue = builder.add("blue", solid(8, 4, [0, 0, 255, 255]));
+        let atlas = builder.build().unwrap();
+
+        for &(sprite, color) in &[(red, [255, 0, 0, 255]), (blue, [0, 0, 255, 255])] {
+            let (x, y, w, h) = pixel_rect(&atlas, sprite);
+            for py in y..y + h {
+                for px in x..x + w {
+                    assert_eq!(atlas.image().get_pixel(px, py).0, color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sprites_dont_overlap() {
+        let mut builder = AtlasBuilder::new();
+        let sprites: Vec<_> = (0..10)
+            .map(|i| builder.add(&i.to_string(), solid(3 + i, 7 - i / 2, [255; 4])))
+            .collect();
+        let atlas = builder.build().unwrap();
+
+        for (i, &a) in sprites.iter().enumerate() {
+            for &b in &sprites[i + 1..] {
+                assert!(!overlaps(pixel_rect(&atlas, a), pixel_rect(&atlas, b)));
+            }
+        }
+    }
+
+    #[test]
+    fn sprites_are_found_by_name() {
+        let mut builder = AtlasBuilder::new();
+        let ship = builder.add("ship", solid(2, 2, [255; 4]));
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.sprite("ship"), Some(ship));
+        assert_eq!(atlas.sprite("rock"), None);
+    }
+
+    #[test]
+    fn too_large() {
+        let mut builder = AtlasBuilder::new().with_max_size(16);
+        builder.add("big", solid(17, 1, [255; 4]));
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    fn sprites_as_wide_as_the_limit_fit() {
+        let mut builder = AtlasBuilder::new().with_max_size(16);
+        let wide = builder.add("wide", solid(16, 2, [255; 4]));
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.image().width(), 16);
+        assert_eq!(atlas.rect(wide).size.x, 1.0);
+    }
+
+    #[test]
+    fn built_atlases_have_unique_ids() {
+        let a = AtlasBuilder::new().build().unwrap();
+        let b = AtlasBuilder::new().build().unwrap();
+        assert_ne!(a.id(), b.id());
+        assert_ne!(a.id(), Atlas::default().id());
+    }
+
+    #[test]
+    fn textures_are_found_by_handle() {
+        let mut textures = Assets::default();
+        let red = textures.add(Texture::new(solid(1, 1, [255, 0, 0, 255])));
+        let blue = textures.add(Texture::new(solid(1, 1, [0, 0, 255, 255])));
+        let atlas = Atlas::from_textures(&textures).unwrap();
+
+        let (x, y, _, _) = pixel_rect(&atlas, atlas.texture(blue.id()).unwrap());
+        assert_eq!(atlas.image().get_pixel(x, y).0, [0, 0, 255, 255]);
+        assert_ne!(atlas.texture(red.id()), atlas.texture(blue.id()));
+    }
+
+    #[test]
+    fn white_texel_is_reserved() {
+        let mut builder = AtlasBuilder::new();
+        builder.add("red", solid(4, 4, [255, 0, 0, 255]));
+        let atlas = builder.build().unwrap();
+
+        let white = atlas.white();
+        assert_eq!(white.size, (0.0, 0.0).into());
+        let (width, height) = atlas.image().dimensions();
+        let x = (white.offset.x * width as f32) as u32;
+        let y = (white.offset.y * height as f32) as u32;
+        assert_eq!(atlas.image().get_pixel(x, y).0, [255, 255, 255, 255]);
+        assert!(!atlas.is_empty());
+    }
+
+    #[test]
+    fn empty_atlases_stay_empty() {
+        assert!(AtlasBuilder::new().build().unwrap().is_empty());
+        assert!(Atlas::default().is_empty());
+    }
+}
@@ -1,24 +1,37 @@
-use crate::lib::{camera, graphics, util};
+use crate::lib::{
+    camera,
+    graphics::{self, DepthTest, ShaderPipeline},
+    shader::{ShaderError, ShaderSource},
+    util,
+};
+
+const VERTEX_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/cube/shader.vert"),
+    include_str!("shader.vert"),
+    util::ShaderStage::Vertex,
+);
+const FRAGMENT_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/cube/shader.frag"),
+    include_str!("shader.frag"),
+    util::ShaderStage::Fragment,
+);
 
 pub struct CubeRenderer {
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     index_count: usize,
+    matrix: cgmath::Matrix4<f32>,
     uniform_buffer: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
-    render_pipeline: wgpu::RenderPipeline,
+    pipeline: ShaderPipeline,
 }
 
 impl CubeRenderer {
-    pub fn new(graphics: &mut graphics::Graphics, camera: &camera::LookAtCamera) -> Self {
-        use std::mem;
-
-        let sc_desc = &graphics.sc_desc;
+    pub fn new(graphics: &mut graphics::Graphics, camera: &camera::LookAtCamera) -> Result<Self, ShaderError> {
         let device = &mut graphics.device;
 
         let mut init_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
 
-        let vertex_size = mem::size_of::<Vertex>();
         let (vertex_data, index_data) = create_vertices();
         let vertex_buffer = device.create_buffer_mapped(vertex_data.len(), wgpu::BufferUsage::VERTEX)
             .fill_from_slice(&vertex_data);
@@ -105,11 +118,11 @@ impl CubeRenderer {
             compare_function: wgpu::CompareFunction::Always,
         });
         let matrix = camera.generate_matrix();
-        let matrix: &[f32; 16] = matrix.as_ref();
+        let mx_ref: &[f32; 16] = matrix.as_ref();
         let uniform_buffer = device.create_buffer_mapped(
             16, wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         )
-            .fill_from_slice(matrix);
+            .fill_from_slice(mx_ref);
         
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
@@ -132,75 +145,36 @@ impl CubeRenderer {
             ],
         });
 
-        let vs_bytes = util::load_glsl(include_str!("shader.vert"), util::ShaderStage::Vertex);
-        let fs_bytes = util::load_glsl(include_str!("shader.frag"), util::ShaderStage::Fragment);
-        let vs_module = device.create_shader_module(&vs_bytes);
-        let fs_module = device.create_shader_module(&fs_bytes);
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            layout: &pipeline_layout,
-            vertex_stage: wgpu::ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: "main",
-            },
-            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: "main",
-            }),
-            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::Back,
-                depth_bias: 0,
-                depth_bias_slope_scale: 0.0,
-                depth_bias_clamp: 0.0,
-            }),
-            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-            color_states: &[wgpu::ColorStateDescriptor {
-                format: sc_desc.format,
-                color_blend: wgpu::BlendDescriptor::REPLACE,
-                alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                write_mask: wgpu::ColorWrite::ALL,
-            }],
-            depth_stencil_state: None,
-            index_format: wgpu::IndexFormat::Uint16,
-            vertex_buffers: &[wgpu::VertexBufferDescriptor {
-                stride: vertex_size as wgpu::BufferAddress,
-                step_mode: wgpu::InputStepMode::Vertex,
-                attributes: &[
-                    wgpu::VertexAttributeDescriptor {
-                        format: wgpu::VertexFormat::Float4,
-                        offset: 0,
-                        shader_location: 0,
-                    },
-                    wgpu::VertexAttributeDescriptor {
-                        format: wgpu::VertexFormat::Float2,
-                        offset: 4 * 4,
-                        shader_location: 1,
-                    },
-                ],
-            }],
-            sample_count: 1,
-            sample_mask: !0,
-            alpha_to_coverage_enabled: false,
-        });
-
         let init_command_buffer = init_encoder.finish();
         device.get_queue().submit(&[init_command_buffer]);
 
-        println!("index_count = {}", index_data.len());
+        let pipeline = ShaderPipeline::new(
+            graphics,
+            pipeline_layout,
+            DepthTest::Enabled,
+            VERTEX_SHADER,
+            FRAGMENT_SHADER,
+            create_render_pipeline,
+        )?;
 
-        Self {
+        Ok(Self {
             vertex_buffer,
             index_buffer,
             index_count: index_data.len(),
+            matrix,
             uniform_buffer,
             bind_group,
-            render_pipeline,
-        }
+            pipeline,
+        })
     }
 
+    /// Uploads a new view projection matrix, if it changed.
     pub fn update_matrix(&mut self, graphics: &mut graphics::Graphics, matrix: &cgmath::Matrix4<f32>) {
-        println!("matrix = {:?}", matrix);
+        if *matrix == self.matrix {
+            return;
+        }
+        self.matrix = *matrix;
+
         let mx_ref: &[f32; 16] = matrix.as_ref();
         let temp_buffer = graphics.device
             .create_buffer_mapped(16, wgpu::BufferUsage::COPY_SRC)
@@ -211,7 +185,7 @@ impl CubeRenderer {
     }
 
     pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
-        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_pipeline(self.pipeline.render_pipeline());
         render_pass.set_bind_group(0, &self.bind_group, &[]);
         render_pass.set_index_buffer(&self.index_buffer, 0);
         render_pass.set_vertex_buffers(0, &[(&self.vertex_buffer, 0)]);
@@ -219,6 +193,84 @@ impl CubeRenderer {
     }
 }
 
+impl graphics::Renderer for CubeRenderer {
+    /// Follows the `LookAtCamera` resource, if there is one.
+    fn prepare(&mut self, graphics: &mut graphics::Graphics, world: &specs::World) {
+        self.pipeline.prepare(graphics);
+        if let Some(camera) = world.try_fetch::<camera::LookAtCamera>() {
+            self.update_matrix(graphics, &camera.generate_matrix());
+        }
+    }
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        CubeRenderer::draw(self, render_pass);
+    }
+
+    fn reload_shaders(&mut self, graphics: &mut graphics::Graphics) {
+        self.pipeline.reload_shaders(graphics);
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    depth_stencil_state: Option<wgpu::DepthStencilStateDescriptor>,
+    sample_count: u32,
+    vs: &[u32],
+    fs: &[u32],
+) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(vs);
+    let fs_module = device.create_shader_module(fs);
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        layout,
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            depth_bias: 0,
+            depth_bias_slope_scale: 0.0,
+            depth_bias_clamp: 0.0,
+        }),
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        color_states: &[wgpu::ColorStateDescriptor {
+            format,
+            color_blend: wgpu::BlendDescriptor::REPLACE,
+            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        depth_stencil_state,
+        index_format: wgpu::IndexFormat::Uint16,
+        vertex_buffers: &[wgpu::VertexBufferDescriptor {
+            stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttributeDescriptor {
+                    format: wgpu::VertexFormat::Float4,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    format: wgpu::VertexFormat::Float2,
+                    offset: 4 * 4,
+                    shader_location: 1,
+                },
+            ],
+        }],
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
 #[derive(Clone, Copy)]
 struct Vertex {
     _pos: [f32; 4],
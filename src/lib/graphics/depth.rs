@@ -0,0 +1,127 @@
+/// How the depth buffer is set up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthConfig {
+    pub format: wgpu::TextureFormat,
+    /// Fragments are drawn when this compares their depth against the depth
+    /// buffer successfully.
+    pub compare: wgpu::CompareFunction,
+    /// What the depth buffer is cleared to at the start of each frame.
+    pub clear_depth: f32,
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            format: wgpu::TextureFormat::Depth32Float,
+            compare: wgpu::CompareFunction::Less,
+            clear_depth: 1.0,
+        }
+    }
+}
+
+/// What a pipeline does with the depth buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthTest {
+    /// Draws the nearest fragments, and records their depth.
+    Enabled,
+    /// Draws everything in order, without touching the depth buffer. This is
+    /// for things like sprites and overlays.
+    Disabled,
+}
+
+impl DepthConfig {
+    /// A depth buffer for `Projection::InfiniteReverseZ`, where nearer
+    /// things have a greater depth.
+    pub fn reverse_z() -> Self {
+        Self {
+            compare: wgpu::CompareFunction::Greater,
+            clear_depth: 0.0,
+            ..Self::default()
+        }
+    }
+
+    /// The depth state for a pipeline drawn into a render pass with this
+    /// depth buffer.
+    pub fn depth_stencil_state(&self, test: DepthTest) -> wgpu::DepthStencilStateDescriptor {
+        let (depth_write_enabled, depth_compare) = match test {
+            DepthTest::Enabled => (true, self.compare),
+            DepthTest::Disabled => (false, wgpu::CompareFunction::Always),
+        };
+        wgpu::DepthStencilStateDescriptor {
+            format: self.format,
+            depth_write_enabled,
+            depth_compare,
+            stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+            stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+            stencil_read_mask: 0,
+            stencil_write_mask: 0,
+        }
+    }
+}
+
+/// A depth texture the size of the render target. `Graphics` recreates it
+/// whenever the target is resized.
+pub struct DepthBuffer {
+    config: DepthConfig,
+    sample_count: u32,
+    view: wgpu::TextureView,
+}
+
+impl DepthBuffer {
+    /// The sample count has to match the color target's.
+    pub fn new(
+        device: &wgpu::Device,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        config: DepthConfig,
+        sample_count: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        });
+        Self {
+            config,
+            sample_count,
+            view: texture.create_default_view(),
+        }
+    }
+
+    pub fn config(&self) -> DepthConfig {
+        self.config
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Attaches the depth buffer to a render pass. The first pass of a frame
+    /// clears it, and later ones load it.
+    pub fn attachment(&self, load_op: wgpu::LoadOp) -> wgpu::RenderPassDepthStencilAttachmentDescriptor<&wgpu::TextureView> {
+        wgpu::RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &self.view,
+            depth_load_op: load_op,
+            depth_store_op: wgpu::StoreOp::Store,
+            stencil_load_op: load_op,
+            stencil_store_op: wgpu::StoreOp::Store,
+            clear_depth: self.config.clear_depth,
+            clear_stencil: 0,
+        }
+    }
+
+    pub(super) fn recreate(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        *self = DepthBuffer::new(device, sc_desc, self.config, self.sample_count);
+    }
+
+    pub(super) fn set_sample_count(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, sample_count: u32) {
+        *self = DepthBuffer::new(device, sc_desc, self.config, sample_count);
+    }
+}
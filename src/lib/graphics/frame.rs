@@ -0,0 +1,84 @@
+use super::{DepthBuffer, FrameTexture, MultisampleTarget};
+
+/// One frame being drawn, from `Graphics::begin_frame`. Render passes are
+/// drawn in the order they're begun, and everything is submitted when the
+/// frame is ended or dropped.
+pub struct Frame<'a> {
+    device: &'a mut wgpu::Device,
+    encoder: Option<wgpu::CommandEncoder>,
+    texture: FrameTexture<'a>,
+    msaa: Option<&'a MultisampleTarget>,
+    depth: Option<&'a DepthBuffer>,
+    clear_color: wgpu::Color,
+    cleared: bool,
+}
+
+impl<'a> Frame<'a> {
+    pub(super) fn new(
+        device: &'a mut wgpu::Device,
+        texture: FrameTexture<'a>,
+        msaa: Option<&'a MultisampleTarget>,
+        depth: Option<&'a DepthBuffer>,
+        clear_color: wgpu::Color,
+    ) -> Self {
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        Self {
+            device,
+            encoder: Some(encoder),
+            texture,
+            msaa,
+            depth,
+            clear_color,
+            cleared: false,
+        }
+    }
+
+    /// Begins a render pass with the frame's depth and multisampling
+    /// attachments. The first pass clears the frame, and later ones draw on
+    /// top of it.
+    pub fn pass(&mut self) -> wgpu::RenderPass {
+        let load_op = if self.cleared {
+            wgpu::LoadOp::Load
+        } else {
+            wgpu::LoadOp::Clear
+        };
+        self.cleared = true;
+
+        let frame = self.texture.view();
+        let (attachment, resolve_target) = match self.msaa {
+            Some(msaa) => (msaa.view(), Some(frame)),
+            None => (frame, None),
+        };
+        let encoder = self.encoder.as_mut().expect("The frame has already been submitted");
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment,
+                resolve_target,
+                load_op,
+                store_op: wgpu::StoreOp::Store,
+                clear_color: self.clear_color,
+            }],
+            depth_stencil_attachment: self.depth.map(|depth| depth.attachment(load_op)),
+        })
+    }
+
+    /// The encoder for work that isn't a render pass, like copies.
+    pub fn encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        self.encoder.as_mut().expect("The frame has already been submitted")
+    }
+
+    /// Submits the frame. Dropping it does the same.
+    pub fn end(self) {}
+}
+
+impl Drop for Frame<'_> {
+    fn drop(&mut self) {
+        // A frame nothing was drawn into still gets cleared
+        if !self.cleared {
+            self.pass();
+        }
+        if let Some(encoder) = self.encoder.take() {
+            self.device.get_queue().submit(&[encoder.finish()]);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Golden image testing for the renderers. A test renders a fixed scene
+//! into a headless `Graphics`, and compares the result against a png
+//! committed in `tests/golden`. A missing reference fails the test. Set
+//! `WGPU_SPECS_BLESS=1` to write the rendered images as the new references
+//! instead, then check and commit them. References have to come from a real
+//! gpu, so a renderer only gets a golden test once one has been blessed.
+
+use std::path::{Path, PathBuf};
+
+const BLESS_VAR: &str = "WGPU_SPECS_BLESS";
+
+pub struct Comparison {
+    pub mismatched: usize,
+    pub diff: image::RgbaImage,
+}
+
+/// Compares two images of the same size. A pixel mismatches when any of its
+/// channels differs by more than `tolerance`. Mismatched pixels are red in
+/// the diff image, and everything else is a faded copy of `actual`.
+pub fn compare(expected: &image::RgbaImage, actual: &image::RgbaImage, tolerance: u8) -> Comparison {
+    assert_eq!(expected.dimensions(), actual.dimensions());
+
+    let mut mismatched = 0;
+    let diff = image::RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let e = expected.get_pixel(x, y).0;
+        let a = actual.get_pixel(x, y).0;
+        let matches = e.iter().zip(a.iter())
+            .all(|(&e, &a)| (e as i16 - a as i16).abs() <= tolerance as i16);
+        if matches {
+            let luma = (a[0] as u32 + a[1] as u32 + a[2] as u32) / 3;
+            let faded = (luma / 4) as u8;
+            image::Rgba([faded, faded, faded, 0xFF])
+        } else {
+            mismatched += 1;
+            image::Rgba([0xFF, 0, 0, 0xFF])
+        }
+    });
+
+    Comparison { mismatched, diff }
+}
+
+/// Compares `actual` against the reference image called `name`, writing the
+/// actual and diff images to `target/golden` when they don't match.
+#[allow(dead_code)]
+pub fn assert_golden(name: &str, actual: &image::RgbaImage, tolerance: u8) {
+    let bless = std::env::var_os(BLESS_VAR).is_some();
+    check_golden(&golden_dir(), &output_dir(), bless, name, actual, tolerance);
+}
+
+fn check_golden(
+    golden_dir: &Path,
+    output_dir: &Path,
+    bless: bool,
+    name: &str,
+    actual: &image::RgbaImage,
+    tolerance: u8,
+) {
+    let reference_path = golden_dir.join(format!("{}.png", name));
+
+    if bless {
+        std::fs::create_dir_all(golden_dir).unwrap();
+        actual.save(&reference_path).unwrap();
+        info!("{}: wrote a new reference to {:?}, check it before committing it", name, reference_path);
+        return;
+    }
+
+    if !reference_path.exists() {
+        panic!(
+            "{}: there is no reference image at {:?}. Render one with {}=1 on a machine with a gpu, \
+             check it, and commit it",
+            name, reference_path, BLESS_VAR,
+        );
+    }
+    let expected = match image::open(&reference_path) {
+        Ok(image) => image.to_rgba(),
+        Err(e) => panic!("{}: unable to load the reference image {:?}: {}", name, reference_path, e),
+    };
+
+    std::fs::create_dir_all(output_dir).unwrap();
+    let actual_path = output_dir.join(format!("{}-actual.png", name));
+
+    if expected.dimensions() != actual.dimensions() {
+        actual.save(&actual_path).unwrap();
+        panic!(
+            "{}: expected a {:?} image but got {:?}, see {:?}",
+            name, expected.dimensions(), actual.dimensions(), actual_path,
+        );
+    }
+
+    let comparison = compare(&expected, actual, tolerance);
+    if comparison.mismatched > 0 {
+        let diff_path = output_dir.join(format!("{}-diff.png", name));
+        actual.save(&actual_path).unwrap();
+        comparison.diff.save(&diff_path).unwrap();
+        panic!(
+            "{}: {} pixels differ from the reference by more than {}, see {:?} and {:?}",
+            name, comparison.mismatched, tolerance, actual_path, diff_path,
+        );
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("golden")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::{graphics::*, util::TempDir};
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 64;
+    const TOLERANCE: u8 = 2;
+
+    fn headless() -> Graphics {
+        Graphics::headless(WIDTH, HEIGHT, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    fn render<F: FnOnce(&mut wgpu::RenderPass)>(graphics: &mut Graphics, draw: F) -> image::RgbaImage {
+        let mut frame = graphics.begin_frame(wgpu::Color {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 1.0,
+        });
+        draw(&mut frame.pass());
+        frame.end();
+        graphics.read_frame().unwrap()
+    }
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba(pixel))
+    }
+
+    #[test]
+    fn compare_identical() {
+        let a = solid(4, 4, [10, 20, 30, 255]);
+        assert_eq!(compare(&a, &a, 0).mismatched, 0);
+    }
+
+    #[test]
+    fn compare_within_tolerance() {
+        let a = solid(4, 4, [10, 20, 30, 255]);
+        let b = solid(4, 4, [12, 18, 30, 255]);
+        assert_eq!(compare(&a, &b, 2).mismatched, 0);
+        assert_eq!(compare(&a, &b, 1).mismatched, 16);
+    }
+
+    #[test]
+    fn compare_marks_mismatches_in_diff() {
+        let a = solid(2, 1, [0, 0, 0, 255]);
+        let mut b = a.clone();
+        b.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+
+        let comparison = compare(&a, &b, 0);
+        assert_eq!(comparison.mismatched, 1);
+        assert_eq!(comparison.diff.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(comparison.diff.get_pixel(1, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    #[should_panic(expected = "there is no reference image")]
+    fn missing_references_fail() {
+        let dir = TempDir::new("golden-missing");
+        check_golden(dir.path(), dir.path(), false, "missing", &solid(2, 2, [0, 0, 0, 255]), 0);
+    }
+
+    #[test]
+    fn blessed_references_are_compared_against() {
+        let dir = TempDir::new("golden-bless");
+        let image = solid(2, 2, [10, 20, 30, 255]);
+        check_golden(dir.path(), dir.path(), true, "bless", &image, 0);
+        assert!(dir.path().join("bless.png").exists());
+        check_golden(dir.path(), dir.path(), false, "bless", &image, 0);
+    }
+
+    #[test]
+    fn mismatches_are_written_out() {
+        let dir = TempDir::new("golden-mismatch");
+        check_golden(dir.path(), dir.path(), true, "mismatch", &solid(2, 2, [0, 0, 0, 255]), 0);
+        let result = std::panic::catch_unwind(|| {
+            check_golden(dir.path(), dir.path(), false, "mismatch", &solid(2, 2, [0, 0, 255, 255]), 0);
+        });
+        assert!(result.is_err());
+        assert!(dir.path().join("mismatch-actual.png").exists());
+        assert!(dir.path().join("mismatch-diff.png").exists());
+    }
+
+    // The renderer tests need a gpu, run them with `cargo test -- --ignored`.
+
+    #[test]
+    #[ignore]
+    fn quad_with_everything_culled() {
+        let mut graphics = headless();
+        let mut renderer = QuadRenderer::new(&mut graphics, 1).unwrap();
+        renderer.update(&mut graphics, &[Instance {
+            offset: (0.0, 0.0).into(),
+            origin: (0.5, 0.5).into(),
+            scale: (1.0, 1.0).into(),
+            rotation: 0.0,
+            color: (1.0, 1.0, 1.0).into(),
+            uv: UvRect::FULL,
+        }]);
+        // Last frame's quad shouldn't be drawn once nothing is visible
+        renderer.update(&mut graphics, &[]);
+        let frame = render(&mut graphics, |rpass| renderer.draw(rpass));
+        assert_eq!(compare(&solid(WIDTH, HEIGHT, [0, 0, 0, 255]), &frame, TOLERANCE).mismatched, 0);
+    }
+
+}
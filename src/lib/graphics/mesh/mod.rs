@@ -0,0 +1,462 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::lib::{
+    assets::{Assets, Handle, HandleId},
+    camera::LookAtCamera,
+    graphics::{self, quad, DepthTest, ShaderPipeline},
+    mesh::{Mesh, MeshVertex},
+    shader::{ShaderError, ShaderSource},
+    util,
+};
+
+const VERTEX_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/mesh/shader.vert"),
+    include_str!("shader.vert"),
+    util::ShaderStage::Vertex,
+);
+const FRAGMENT_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/mesh/shader.frag"),
+    include_str!("shader.frag"),
+    util::ShaderStage::Fragment,
+);
+
+/// A mesh to draw this frame, usually built by the `MeshInstanceSystem`.
+#[derive(Debug, Clone)]
+pub struct MeshInstance {
+    pub mesh: Handle<Mesh>,
+    /// Takes the mesh from its own space to the world.
+    pub model: cgmath::Matrix4<f32>,
+}
+
+/// Draws `Mesh` assets with a directional light, in 3d. Meshes are uploaded
+/// the first time they're drawn, and every instance of a mesh is drawn in
+/// one call per submesh. The `App` runs the `TransformSystem` and the
+/// `MeshInstanceSystem` every frame, so entities only need a `Transform` and
+/// a `MeshHandle` to be drawn.
+pub struct MeshRenderer {
+    uniform_buffer: wgpu::Buffer,
+    view_proj: cgmath::Matrix4<f32>,
+    globals_bind_group: wgpu::BindGroup,
+    material_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    white: wgpu::TextureView,
+    meshes: HashMap<HandleId, GpuMesh>,
+    meshes_version: Option<u64>,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    /// The instances of each mesh, in the order they are in the instance
+    /// buffer.
+    batches: Vec<(HandleId, Range<u32>)>,
+    pipeline: ShaderPipeline,
+}
+
+impl MeshRenderer {
+    /// Creates a renderer with room for `capacity` instances. The instance
+    /// buffer grows when more are needed.
+    pub fn new(graphics: &mut graphics::Graphics, camera: &LookAtCamera, capacity: usize) -> Result<Self, ShaderError> {
+        let device = &mut graphics.device;
+
+        let mut init_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            bindings: &[
+                wgpu::BindGroupLayoutBinding {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                    },
+                },
+            ],
+        });
+        let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            bindings: &[
+                wgpu::BindGroupLayoutBinding {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                    },
+                },
+                wgpu::BindGroupLayoutBinding {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                    },
+                },
+                wgpu::BindGroupLayoutBinding {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&globals_layout, &material_layout],
+        });
+
+        let view_proj = camera.generate_matrix();
+        let uniform_buffer = device
+            .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST)
+            .fill_from_slice(&[GlobalUniforms { view_proj }]);
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &globals_layout,
+            bindings: &[
+                wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer {
+                        buffer: &uniform_buffer,
+                        range: 0..64,
+                    },
+                },
+            ],
+        });
+
+        // Materials without a texture sample this, leaving their base color
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let white = quad::create_texture(device, &mut init_encoder, &white);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare_function: wgpu::CompareFunction::Always,
+        });
+
+        let instance_buffer = create_instance_buffer(device, capacity);
+
+        let init_command_buffer = init_encoder.finish();
+        device.get_queue().submit(&[init_command_buffer]);
+
+        let pipeline = ShaderPipeline::new(
+            graphics,
+            pipeline_layout,
+            DepthTest::Enabled,
+            VERTEX_SHADER,
+            FRAGMENT_SHADER,
+            create_render_pipeline,
+        )?;
+
+        Ok(Self {
+            uniform_buffer,
+            view_proj,
+            globals_bind_group,
+            material_layout,
+            sampler,
+            white,
+            meshes: HashMap::new(),
+            meshes_version: None,
+            instance_buffer,
+            instance_capacity: capacity,
+            batches: Vec::new(),
+            pipeline,
+        })
+    }
+
+    /// Draws the meshes as seen by `camera`.
+    pub fn set_camera(&mut self, graphics: &mut graphics::Graphics, camera: &LookAtCamera) {
+        let view_proj = camera.generate_matrix();
+        if view_proj == self.view_proj {
+            return;
+        }
+        self.view_proj = view_proj;
+
+        let temp_buffer = graphics.device
+            .create_buffer_mapped(1, wgpu::BufferUsage::COPY_SRC)
+            .fill_from_slice(&[GlobalUniforms { view_proj }]);
+        let mut encoder = graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        let size = std::mem::size_of::<GlobalUniforms>() as wgpu::BufferAddress;
+        encoder.copy_buffer_to_buffer(&temp_buffer, 0, &self.uniform_buffer, 0, size);
+        graphics.device.get_queue().submit(&[encoder.finish()]);
+    }
+
+    /// Uploads the instances, and any of their meshes that haven't been
+    /// drawn before. Instances of meshes that aren't in `meshes` are
+    /// skipped, and meshes that were unloaded are dropped from the gpu.
+    pub fn update(&mut self, graphics: &mut graphics::Graphics, meshes: &Assets<Mesh>, instances: &[MeshInstance]) {
+        if self.meshes_version != Some(meshes.version()) {
+            self.meshes_version = Some(meshes.version());
+            let loaded: HashSet<HandleId> = meshes.iter().map(|(id, _)| id).collect();
+            self.meshes.retain(|id, _| loaded.contains(id));
+        }
+
+        let mut sorted: Vec<&MeshInstance> = instances.iter().collect();
+        sorted.sort_by_key(|instance| instance.mesh.id());
+
+        self.batches.clear();
+        let mut models: Vec<cgmath::Matrix4<f32>> = Vec::with_capacity(sorted.len());
+        for instance in sorted {
+            let id = instance.mesh.id();
+            if !self.meshes.contains_key(&id) {
+                let mesh = match meshes.get(&instance.mesh) {
+                    Some(mesh) => mesh,
+                    None => continue,
+                };
+                let gpu_mesh = GpuMesh::new(&mut graphics.device, &self.material_layout, &self.sampler, &self.white, mesh);
+                self.meshes.insert(id, gpu_mesh);
+            }
+
+            let index = models.len() as u32;
+            match self.batches.last_mut() {
+                Some((batch_id, range)) if *batch_id == id => range.end = index + 1,
+                _ => self.batches.push((id, index..index + 1)),
+            }
+            models.push(instance.model);
+        }
+
+        if models.len() > self.instance_capacity {
+            self.instance_capacity = quad::grown_capacity(self.instance_capacity, models.len(), None);
+            info!("Growing the mesh instance buffer to {} instances", self.instance_capacity);
+            self.instance_buffer = create_instance_buffer(&graphics.device, self.instance_capacity);
+        }
+
+        if !models.is_empty() {
+            let buffer_size = (models.len() * std::mem::size_of::<cgmath::Matrix4<f32>>()) as u64;
+            let temp_buffer = graphics.device
+                .create_buffer_mapped(models.len(), wgpu::BufferUsage::COPY_SRC)
+                .fill_from_slice(&models);
+
+            let mut encoder = graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+            encoder.copy_buffer_to_buffer(&temp_buffer, 0, &self.instance_buffer, 0, buffer_size);
+            graphics.device.get_queue().submit(&[encoder.finish()]);
+        }
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(self.pipeline.render_pipeline());
+        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+        for (id, instances) in &self.batches {
+            for submesh in &self.meshes[id].submeshes {
+                render_pass.set_bind_group(1, &submesh.bind_group, &[]);
+                render_pass.set_index_buffer(&submesh.index_buffer, 0);
+                render_pass.set_vertex_buffers(0, &[(&submesh.vertex_buffer, 0), (&self.instance_buffer, 0)]);
+                render_pass.draw_indexed(0..submesh.index_count, 0, instances.clone());
+            }
+        }
+    }
+}
+
+impl graphics::Renderer for MeshRenderer {
+    /// Follows the `LookAtCamera` resource, and draws the `Vec<MeshInstance>`
+    /// resource with the meshes in `Assets<Mesh>`, if there are both.
+    fn prepare(&mut self, graphics: &mut graphics::Graphics, world: &specs::World) {
+        self.pipeline.prepare(graphics);
+        if let Some(camera) = world.try_fetch::<LookAtCamera>() {
+            self.set_camera(graphics, &camera);
+        }
+        if let (Some(meshes), Some(instances)) = (world.try_fetch::<Assets<Mesh>>(), world.try_fetch::<Vec<MeshInstance>>()) {
+            self.update(graphics, &meshes, &instances);
+        }
+    }
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        MeshRenderer::draw(self, render_pass);
+    }
+
+    fn reload_shaders(&mut self, graphics: &mut graphics::Graphics) {
+        self.pipeline.reload_shaders(graphics);
+    }
+}
+
+/// A mesh's buffers and materials on the gpu.
+struct GpuMesh {
+    submeshes: Vec<GpuSubmesh>,
+}
+
+struct GpuSubmesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    // Kept alive for the bind group
+    _material_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GpuMesh {
+    fn new(
+        device: &mut wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        white: &wgpu::TextureView,
+        mesh: &Mesh,
+    ) -> Self {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        let textures: Vec<wgpu::TextureView> = mesh.textures.iter()
+            .map(|image| quad::create_texture(device, &mut encoder, image))
+            .collect();
+
+        let submeshes = mesh.submeshes.iter()
+            .filter(|submesh| !submesh.indices.is_empty())
+            .map(|submesh| {
+                let vertex_buffer = device
+                    .create_buffer_mapped(submesh.vertices.len(), wgpu::BufferUsage::VERTEX)
+                    .fill_from_slice(&submesh.vertices);
+                let index_buffer = device
+                    .create_buffer_mapped(submesh.indices.len(), wgpu::BufferUsage::INDEX)
+                    .fill_from_slice(&submesh.indices);
+                let material_buffer = device
+                    .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM)
+                    .fill_from_slice(&[MaterialUniforms { base_color: submesh.material.base_color }]);
+                let texture = submesh.material.base_color_texture
+                    .and_then(|index| textures.get(index))
+                    .unwrap_or(white);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout,
+                    bindings: &[
+                        wgpu::Binding {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Buffer {
+                                buffer: &material_buffer,
+                                range: 0..std::mem::size_of::<MaterialUniforms>() as wgpu::BufferAddress,
+                            },
+                        },
+                        wgpu::Binding {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(texture),
+                        },
+                        wgpu::Binding {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                    ],
+                });
+                GpuSubmesh {
+                    vertex_buffer,
+                    index_buffer,
+                    index_count: submesh.indices.len() as u32,
+                    _material_buffer: material_buffer,
+                    bind_group,
+                }
+            })
+            .collect();
+
+        device.get_queue().submit(&[encoder.finish()]);
+        Self { submeshes }
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    depth_stencil_state: Option<wgpu::DepthStencilStateDescriptor>,
+    sample_count: u32,
+    vs: &[u32],
+    fs: &[u32],
+) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(vs);
+    let fs_module = device.create_shader_module(fs);
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        layout,
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            depth_bias: 0,
+            depth_bias_slope_scale: 0.0,
+            depth_bias_clamp: 0.0,
+        }),
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        color_states: &[wgpu::ColorStateDescriptor {
+            format,
+            color_blend: wgpu::BlendDescriptor::REPLACE,
+            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        depth_stencil_state,
+        index_format: wgpu::IndexFormat::Uint32,
+        vertex_buffers: &[
+            wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &[
+                    // position
+                    wgpu::VertexAttributeDescriptor {
+                        format: wgpu::VertexFormat::Float3,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    // normal
+                    wgpu::VertexAttributeDescriptor {
+                        format: wgpu::VertexFormat::Float3,
+                        offset: 4 * 3,
+                        shader_location: 1,
+                    },
+                    // tex_coord
+                    wgpu::VertexAttributeDescriptor {
+                        format: wgpu::VertexFormat::Float2,
+                        offset: 4 * 6,
+                        shader_location: 2,
+                    },
+                ],
+            },
+            wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<cgmath::Matrix4<f32>>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Instance,
+                // The model matrix, a column at a time
+                attributes: &[
+                    wgpu::VertexAttributeDescriptor {
+                        format: wgpu::VertexFormat::Float4,
+                        offset: 0,
+                        shader_location: 3,
+                    },
+                    wgpu::VertexAttributeDescriptor {
+                        format: wgpu::VertexFormat::Float4,
+                        offset: 4 * 4,
+                        shader_location: 4,
+                    },
+                    wgpu::VertexAttributeDescriptor {
+                        format: wgpu::VertexFormat::Float4,
+                        offset: 4 * 8,
+                        shader_location: 5,
+                    },
+                    wgpu::VertexAttributeDescriptor {
+                        format: wgpu::VertexFormat::Float4,
+                        offset: 4 * 12,
+                        shader_location: 6,
+                    },
+                ],
+            },
+        ],
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        size: (std::mem::size_of::<cgmath::Matrix4<f32>>() * capacity.max(1)) as u64,
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GlobalUniforms {
+    view_proj: cgmath::Matrix4<f32>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MaterialUniforms {
+    base_color: [f32; 4],
+}
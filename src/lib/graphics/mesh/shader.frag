@@ -0,0 +1,22 @@
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec2 v_tex_coord;
+
+layout(location = 0) out vec4 o_target;
+
+layout(set = 1, binding = 0) uniform Material {
+    vec4 u_base_color;
+};
+layout(set = 1, binding = 1) uniform texture2D t_base_color;
+layout(set = 1, binding = 2) uniform sampler s_base_color;
+
+// A light shining down at an angle, with z up like LookAtCamera
+const vec3 LIGHT_DIRECTION = vec3(0.259, -0.432, 0.864);
+const float AMBIENT = 0.3;
+
+void main() {
+    vec4 color = u_base_color * texture(sampler2D(t_base_color, s_base_color), v_tex_coord);
+    float diffuse = max(dot(normalize(v_normal), LIGHT_DIRECTION), 0.0);
+    o_target = vec4(color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), color.a);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+layout(location = 2) in vec2 a_tex_coord;
+layout(location = 3) in vec4 a_model_0;
+layout(location = 4) in vec4 a_model_1;
+layout(location = 5) in vec4 a_model_2;
+layout(location = 6) in vec4 a_model_3;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec2 v_tex_coord;
+
+layout(set = 0, binding = 0) uniform Globals {
+    mat4 u_view_proj;
+};
+
+void main() {
+    mat4 model = mat4(a_model_0, a_model_1, a_model_2, a_model_3);
+    // Normals need the inverse transpose to stay perpendicular under
+    // non-uniform scales
+    mat3 normal_matrix = transpose(inverse(mat3(model)));
+    v_normal = normalize(normal_matrix * a_normal);
+    v_tex_coord = a_tex_coord;
+    gl_Position = u_view_proj * model * vec4(a_position, 1.0);
+}
@@ -1,19 +1,60 @@
 use winit::{event_loop::EventLoop, window::Window};
 
+use crate::lib::shader::ShaderCache;
+
+mod atlas;
 mod cube;
+mod depth;
+mod frame;
+#[cfg(test)]
+mod golden;
+mod mesh;
+mod msaa;
+mod pipeline;
+mod target;
 mod triangle;
 mod quad;
 
+pub use atlas::*;
 pub use cube::*;
+pub use depth::*;
+pub use frame::*;
+pub use mesh::*;
+pub use msaa::*;
+pub use pipeline::*;
+pub use target::*;
 pub use triangle::*;
 pub use quad::*;
 
+/// Set this to a directory to keep compiled shaders there between runs.
+/// Without it shaders are only cached in memory.
+const SHADER_CACHE_VAR: &str = "WGPU_SPECS_SHADER_CACHE";
+
+/// Something the `App` can draw every frame.
+pub trait Renderer {
+    /// Uploads whatever changed in the world before drawing.
+    fn prepare(&mut self, _graphics: &mut Graphics, _world: &specs::World) {}
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass);
+    /// Rebuilds pipelines whose shader files changed, keeping the current
+    /// ones if the new shaders don't compile. The `App` only calls this
+    /// with the `hot-reload` feature.
+    fn reload_shaders(&mut self, _graphics: &mut Graphics) {}
+}
+
 pub struct Graphics {
     adapter: wgpu::Adapter,
     pub device: wgpu::Device,
-    surface: wgpu::Surface,
     pub sc_desc: wgpu::SwapChainDescriptor,
-    pub swap_chain: wgpu::SwapChain,
+    pub target: RenderTarget,
+    /// Renderers that draw in 3d attach this to their render passes.
+    pub depth: Option<DepthBuffer>,
+    /// Where frames are drawn when multisampling, before being resolved into
+    /// the target.
+    pub msaa: Option<MultisampleTarget>,
+    /// Kept on disk in the directory named by `WGPU_SPECS_SHADER_CACHE` if
+    /// it's set, and in memory otherwise.
+    pub shader_cache: ShaderCache,
 
     hidpi_factor: f64,
     size: winit::dpi::PhysicalSize,
@@ -57,16 +98,7 @@ impl Graphics {
         //     power_preference: wgpu::PowerPreference::LowPower,
         //     backends: wgpu::BackendBit::PRIMARY,
         // }).unwrap();
-        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::LowPower,
-        });
-
-        let device = adapter.request_device(&wgpu::DeviceDescriptor {
-            extensions: wgpu::Extensions {
-                anisotropic_filtering: false,
-            },
-            limits: wgpu::Limits::default(),
-        });
+        let (adapter, device) = request_device(&instance);
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
@@ -82,8 +114,10 @@ impl Graphics {
                 adapter,
                 device,
                 sc_desc,
-                swap_chain,
-                surface,
+                target: RenderTarget::Window { surface, swap_chain },
+                depth: None,
+                msaa: None,
+                shader_cache: shader_cache(),
                 size,
                 hidpi_factor,
             }, 
@@ -91,15 +125,152 @@ impl Graphics {
         )
     }
 
+    /// Creates a `Graphics` that renders into an offscreen texture instead of
+    /// a window. Use `read_frame` to get the rendered image back, which
+    /// needs an 8-bit rgba or bgra `format`. Panics with any other format.
+    pub fn headless(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        assert!(
+            Offscreen::supports_format(format),
+            "Headless rendering needs an 8-bit rgba or bgra format, not {:?}",
+            format,
+        );
+        let instance = wgpu::Instance::new();
+        let (adapter, device) = request_device(&instance);
+
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Vsync,
+        };
+        let offscreen = Offscreen::new(&device, &sc_desc);
+
+        Self {
+            adapter,
+            device,
+            sc_desc,
+            target: RenderTarget::Offscreen(offscreen),
+            depth: None,
+            msaa: None,
+            shader_cache: shader_cache(),
+            size: winit::dpi::PhysicalSize::new(width as f64, height as f64),
+            hidpi_factor: 1.0,
+        }
+    }
+
     pub fn resize(&mut self, size: winit::dpi::LogicalSize) {
         let physical = size.to_physical(self.hidpi_factor);
         info!("Resising to {:?}", physical);
         self.sc_desc.width = physical.width.round() as u32;
         self.sc_desc.height = physical.height.round() as u32;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        self.size = physical;
+        self.target.recreate(&self.device, &self.sc_desc);
+        if let Some(depth) = &mut self.depth {
+            depth.recreate(&self.device, &self.sc_desc);
+        }
+        if let Some(msaa) = &mut self.msaa {
+            msaa.recreate(&self.device, &self.sc_desc);
+        }
+    }
+
+    pub fn with_depth(mut self, config: DepthConfig) -> Self {
+        self.set_depth(Some(config));
+        self
+    }
+
+    /// Adds or removes the depth buffer. Renderers rebuild their pipelines to
+    /// match when they're next prepared.
+    pub fn set_depth(&mut self, config: Option<DepthConfig>) {
+        let sample_count = self.sample_count();
+        self.depth = config.map(|config| DepthBuffer::new(&self.device, &self.sc_desc, config, sample_count));
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.set_sample_count(sample_count);
+        self
+    }
+
+    /// Sets how many samples each pixel gets, where 1 turns multisampling
+    /// off. Only 1, 2, 4 and 8 are supported, and other counts are lowered to
+    /// the nearest of those with a warning. Renderers rebuild their pipelines
+    /// to match when they're next prepared.
+    pub fn set_sample_count(&mut self, requested: u32) {
+        let sample_count = msaa::supported_sample_count(requested);
+        if sample_count != requested {
+            warn!("{} samples per pixel aren't supported, using {}", requested, sample_count);
+        }
+        if sample_count == self.sample_count() {
+            return;
+        }
+        info!("Using {} samples per pixel", sample_count);
+        self.msaa = if sample_count > 1 {
+            Some(MultisampleTarget::new(&self.device, &self.sc_desc, sample_count))
+        } else {
+            None
+        };
+        if let Some(depth) = &mut self.depth {
+            depth.set_sample_count(&self.device, &self.sc_desc, sample_count);
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.msaa.as_ref().map_or(1, MultisampleTarget::sample_count)
+    }
+
+    pub fn depth_config(&self) -> Option<DepthConfig> {
+        self.depth.as_ref().map(DepthBuffer::config)
+    }
+
+    /// The depth state pipelines need to be drawn into the render pass,
+    /// which is `None` without a depth buffer.
+    pub fn depth_stencil_state(&self, test: DepthTest) -> Option<wgpu::DepthStencilStateDescriptor> {
+        self.depth.as_ref().map(|depth| depth.config().depth_stencil_state(test))
+    }
+
+    /// Starts drawing the next frame, which gets cleared to `clear_color`.
+    pub fn begin_frame(&mut self, clear_color: wgpu::Color) -> Frame {
+        Frame::new(
+            &mut self.device,
+            self.target.next_texture(),
+            self.msaa.as_ref(),
+            self.depth.as_ref(),
+            clear_color,
+        )
     }
 
     pub fn aspect_ratio(&self) -> f32 {
         self.sc_desc.width as f32 / self.sc_desc.height as f32
     }
-}
\ No newline at end of file
+
+    /// Reads the last rendered frame back from the gpu. Only headless
+    /// graphics can do this, as the swap chain can't be copied from.
+    pub fn read_frame(&mut self) -> Option<image::RgbaImage> {
+        match &self.target {
+            RenderTarget::Offscreen(offscreen) => Some(offscreen.read(&mut self.device, &self.sc_desc)),
+            RenderTarget::Window { .. } => None,
+        }
+    }
+}
+
+fn request_device(instance: &wgpu::Instance) -> (wgpu::Adapter, wgpu::Device) {
+    let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::LowPower,
+    });
+
+    let device = adapter.request_device(&wgpu::DeviceDescriptor {
+        extensions: wgpu::Extensions {
+            anisotropic_filtering: false,
+        },
+        limits: wgpu::Limits::default(),
+    });
+
+    (adapter, device)
+}
+
+fn shader_cache() -> ShaderCache {
+    match std::env::var_os(SHADER_CACHE_VAR) {
+        Some(ref dir) if !dir.is_empty() => ShaderCache::with_directory(dir),
+        _ => ShaderCache::in_memory(),
+    }
+}
@@ -0,0 +1,68 @@
+/// The sample counts multisampling supports.
+const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+/// A multisampled color texture the size of the render target. Frames are
+/// drawn into it, then resolved into the frame texture.
+pub struct MultisampleTarget {
+    sample_count: u32,
+    view: wgpu::TextureView,
+}
+
+impl MultisampleTarget {
+    pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, sample_count: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: sc_desc.format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        });
+        Self {
+            sample_count,
+            view: texture.create_default_view(),
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub(super) fn recreate(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        *self = MultisampleTarget::new(device, sc_desc, self.sample_count);
+    }
+}
+
+/// The largest supported sample count that isn't more than `requested`.
+pub(super) fn supported_sample_count(requested: u32) -> u32 {
+    SAMPLE_COUNTS.iter().rev().cloned().find(|&count| count <= requested).unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_sample_counts_are_kept() {
+        for &count in &SAMPLE_COUNTS {
+            assert_eq!(supported_sample_count(count), count);
+        }
+    }
+
+    #[test]
+    fn other_sample_counts_are_lowered() {
+        assert_eq!(supported_sample_count(0), 1);
+        assert_eq!(supported_sample_count(3), 2);
+        assert_eq!(supported_sample_count(7), 4);
+        assert_eq!(supported_sample_count(16), 8);
+    }
+}
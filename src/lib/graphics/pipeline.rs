@@ -0,0 +1,115 @@
+use crate::lib::shader::{ShaderError, ShaderSource, ShaderWatcher};
+
+use super::{DepthConfig, DepthTest, Graphics};
+
+/// Creates a renderer's pipeline from its layout, the target format, the
+/// depth state and sample count, and the compiled vertex and fragment
+/// shaders.
+pub type CreatePipeline = fn(
+    &wgpu::Device,
+    &wgpu::PipelineLayout,
+    wgpu::TextureFormat,
+    Option<wgpu::DepthStencilStateDescriptor>,
+    u32,
+    &[u32],
+    &[u32],
+) -> wgpu::RenderPipeline;
+
+/// A renderer's pipeline, along with everything needed to recreate it when
+/// its shaders change or `Graphics` starts drawing differently.
+pub struct ShaderPipeline {
+    layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    depth_test: DepthTest,
+    depth: Option<DepthConfig>,
+    sample_count: u32,
+    spirv: Vec<Vec<u32>>,
+    shader_watcher: ShaderWatcher,
+    create: CreatePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl std::fmt::Debug for ShaderPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ShaderPipeline")
+            .field("format", &self.format)
+            .field("depth_test", &self.depth_test)
+            .field("depth", &self.depth)
+            .field("sample_count", &self.sample_count)
+            .finish()
+    }
+}
+
+impl ShaderPipeline {
+    pub fn new(
+        graphics: &mut Graphics,
+        layout: wgpu::PipelineLayout,
+        depth_test: DepthTest,
+        vertex_shader: ShaderSource,
+        fragment_shader: ShaderSource,
+        create: CreatePipeline,
+    ) -> Result<Self, ShaderError> {
+        let spirv = vec![
+            vertex_shader.compile(&mut graphics.shader_cache)?,
+            fragment_shader.compile(&mut graphics.shader_cache)?,
+        ];
+        let format = graphics.sc_desc.format;
+        let depth = graphics.depth_config();
+        let sample_count = graphics.sample_count();
+        let render_pipeline = create(
+            &graphics.device,
+            &layout,
+            format,
+            graphics.depth_stencil_state(depth_test),
+            sample_count,
+            &spirv[0],
+            &spirv[1],
+        );
+
+        Ok(Self {
+            layout,
+            format,
+            depth_test,
+            depth,
+            sample_count,
+            spirv,
+            shader_watcher: ShaderWatcher::new(&[vertex_shader, fragment_shader]),
+            create,
+            render_pipeline,
+        })
+    }
+
+    pub fn render_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.render_pipeline
+    }
+
+    /// Recreates the pipeline if `graphics` changed its depth buffer or
+    /// sample count since it was last made.
+    pub fn prepare(&mut self, graphics: &Graphics) {
+        if self.depth != graphics.depth_config() || self.sample_count != graphics.sample_count() {
+            self.rebuild(graphics);
+        }
+    }
+
+    /// Recreates the pipeline if its shader files changed and still compile.
+    pub fn reload_shaders(&mut self, graphics: &Graphics) {
+        if let Some(spirv) = self.shader_watcher.reload() {
+            self.spirv = spirv;
+            self.rebuild(graphics);
+        }
+    }
+
+    fn rebuild(&mut self, graphics: &Graphics) {
+        self.depth = graphics.depth_config();
+        self.sample_count = graphics.sample_count();
+        self.render_pipeline = (self.create)(
+            &graphics.device,
+            &self.layout,
+            self.format,
+            graphics.depth_stencil_state(self.depth_test),
+            self.sample_count,
+            &self.spirv[0],
+            &self.spirv[1],
+        );
+    }
+}
@@ -1,4 +1,27 @@
-use crate::lib::{graphics, util};
+use crate::lib::{
+    camera::{Bounds, Camera2d},
+    graphics::{self, Atlas, DepthTest, ShaderPipeline, UvRect},
+    shader::{ShaderError, ShaderSource},
+    util,
+};
+
+mod software;
+
+pub use software::*;
+
+const VERTEX_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/quad/shader.vert"),
+    include_str!("shader.vert"),
+    util::ShaderStage::Vertex,
+);
+const FRAGMENT_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/quad/shader.frag"),
+    include_str!("shader.frag"),
+    util::ShaderStage::Fragment,
+);
+
+/// How much bigger the instance buffer gets each time it runs out of room.
+const GROWTH_FACTOR: usize = 2;
 
 #[derive(Debug)]
 pub struct QuadRenderer {
@@ -6,81 +29,33 @@ pub struct QuadRenderer {
     vertex_count: usize,
     instance_buffer: wgpu::Buffer,
     instance_count: usize,
-    max_instances: usize,
+    instance_capacity: usize,
+    max_instances: Option<usize>,
+    truncating: bool,
     uniform_buffer: wgpu::Buffer,
+    view_proj: cgmath::Matrix4<f32>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
     bind_group: wgpu::BindGroup,
-    render_pipeline: wgpu::RenderPipeline,
+    atlas_id: Option<u64>,
+    pipeline: ShaderPipeline,
 }
 
 impl QuadRenderer {
-    pub fn new(graphics: &mut graphics::Graphics, max_instances: usize) -> Self {
-        use std::mem;
-
-        let sc_desc = &graphics.sc_desc;
+    /// Creates a renderer with room for `capacity` instances. The instance
+    /// buffer grows when more are needed.
+    pub fn new(graphics: &mut graphics::Graphics, capacity: usize) -> Result<Self, ShaderError> {
         let device = &mut graphics.device;
 
         let mut init_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
 
-        let vertex_size = mem::size_of::<Vertex>();
         let vertex_data = create_quad();
         let vertex_buffer = device
             .create_buffer_mapped(vertex_data.len(), wgpu::BufferUsage::VERTEX)
             .fill_from_slice(&vertex_data);
         let vertex_count = vertex_data.len();
-        let vb_desc = wgpu::VertexBufferDescriptor {
-            stride: vertex_size  as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttributeDescriptor {
-                    format: wgpu::VertexFormat::Float2,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttributeDescriptor {
-                    format: wgpu::VertexFormat::Float2,
-                    offset: 2 * mem::size_of::<f32>() as u64,
-                    shader_location: 1,
-                },
-            ],
-        };
 
-        let instance_size = mem::size_of::<Instance>();
-        let instance_buffer = device
-            .create_buffer(&wgpu::BufferDescriptor {
-                size: (instance_size * max_instances) as u64,
-                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
-            });
-        let ib_desc = wgpu::VertexBufferDescriptor {
-            stride: instance_size as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttributeDescriptor {
-                    format: wgpu::VertexFormat::Float2,
-                    offset: 0,
-                    shader_location: 2,
-                },
-                wgpu::VertexAttributeDescriptor {
-                    format: wgpu::VertexFormat::Float2,
-                    offset: mem::size_of::<cgmath::Vector2<f32>>() as u64,
-                    shader_location: 3,
-                },
-                wgpu::VertexAttributeDescriptor {
-                    format: wgpu::VertexFormat::Float2,
-                    offset: 2 * mem::size_of::<cgmath::Vector2<f32>>() as u64,
-                    shader_location: 4,
-                },
-                wgpu::VertexAttributeDescriptor {
-                    format: wgpu::VertexFormat::Float,
-                    offset: 3 * mem::size_of::<cgmath::Vector2<f32>>() as u64,
-                    shader_location: 5,
-                },
-                wgpu::VertexAttributeDescriptor {
-                    format: wgpu::VertexFormat::Float3,
-                    offset: (3 * mem::size_of::<cgmath::Vector2<f32>>() + mem::size_of::<f32>()) as u64,
-                    shader_location: 6,
-                },
-            ],
-        };
+        let instance_buffer = create_instance_buffer(device, capacity);
         
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             bindings: &[
@@ -110,49 +85,9 @@ impl QuadRenderer {
             bind_group_layouts: &[&bind_group_layout],
         });
 
-        use image::GenericImageView;
-        let image = image::load_from_memory_with_format(include_bytes!("rust.png"), image::ImageFormat::PNG).unwrap();
-        let image_dimensions = image.dimensions();
-        let texture_data = image.as_rgba8().unwrap().to_vec();
-        let texture_extent = wgpu::Extent3d {
-            width: image_dimensions.0,
-            height: image_dimensions.0,
-            depth: 1,
-        };
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_extent,
-            array_layer_count: 1,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-        });
-        let texture_view = texture.create_default_view();
-        let temp_buffer = device.create_buffer_mapped(
-            texture_data.len(), 
-            wgpu::BufferUsage::COPY_SRC
-        )
-            .fill_from_slice(&texture_data);
-        init_encoder.copy_buffer_to_texture(
-            wgpu::BufferCopyView {
-                buffer: &temp_buffer,
-                offset: 0,
-                row_pitch: 4 * image_dimensions.0,
-                image_height: image_dimensions.1,
-            },
-            wgpu::TextureCopyView {
-                texture: &texture,
-                mip_level: 0,
-                array_layer: 0,
-                origin: wgpu::Origin3d {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                },
-            },
-            texture_extent,
-        );
+        // Until there's an atlas, everything is drawn in its plain color
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let texture_view = create_texture(device, &mut init_encoder, &white);
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -165,100 +100,119 @@ impl QuadRenderer {
             compare_function: wgpu::CompareFunction::Always,
         });
 
-        let global_uniform_size = mem::size_of::<GlobalUniforms>() as wgpu::BufferAddress;
-        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            size: global_uniform_size,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            bindings: &[
-                wgpu::Binding {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer {
-                        buffer: &uniform_buffer,
-                        range: 0..64,
-                    },
-                },
-                wgpu::Binding {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::Binding {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
-
-        let vs_bytes = util::load_glsl(include_str!("shader.vert"), util::ShaderStage::Vertex);
-        let fs_bytes = util::load_glsl(include_str!("shader.frag"), util::ShaderStage::Fragment);
-        let vs_module = device.create_shader_module(&vs_bytes);
-        let fs_module = device.create_shader_module(&fs_bytes);
+        // Until there's a camera, instances are positioned in clip space
+        let view_proj = Camera2d::default().view_projection();
+        let uniform_buffer = device
+            .create_buffer_mapped(1, wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST)
+            .fill_from_slice(&[GlobalUniforms { view_proj }]);
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            layout: &pipeline_layout,
-            vertex_stage: wgpu::ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: "main",
-            },
-            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: "main",
-            }),
-            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::None,
-                depth_bias: 0,
-                depth_bias_slope_scale: 0.0,
-                depth_bias_clamp: 0.0,
-            }),
-            primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
-            color_states: &[
-                wgpu::ColorStateDescriptor {
-                    format: sc_desc.format,
-                    color_blend: wgpu::BlendDescriptor {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                    alpha_blend: wgpu::BlendDescriptor {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                    write_mask: wgpu::ColorWrite::ALL,
-                }
-            ],
-            depth_stencil_state: None,
-            index_format: wgpu::IndexFormat::Uint16,
-            vertex_buffers: &[vb_desc, ib_desc],
-            sample_count: 1,
-            sample_mask: !0,
-            alpha_to_coverage_enabled: false,
-        });
+        let bind_group = create_bind_group(device, &bind_group_layout, &uniform_buffer, &texture_view, &sampler);
 
         let init_command_buffer = init_encoder.finish();
         device.get_queue().submit(&[init_command_buffer]);
 
-        Self {
+        // Quads are drawn in order, on top of whatever is already there
+        let pipeline = ShaderPipeline::new(
+            graphics,
+            pipeline_layout,
+            DepthTest::Disabled,
+            VERTEX_SHADER,
+            FRAGMENT_SHADER,
+            create_render_pipeline,
+        )?;
+
+        Ok(Self {
             vertex_buffer,
             vertex_count,
             instance_buffer,
             instance_count: 0,
-            max_instances,
+            instance_capacity: capacity,
+            max_instances: None,
+            truncating: false,
             uniform_buffer,
+            view_proj,
+            bind_group_layout,
+            sampler,
             bind_group,
-            render_pipeline,
+            atlas_id: None,
+            pipeline,
+        })
+    }
+
+    /// Draws instances with `atlas`. Their uv rects should come from it.
+    pub fn with_atlas(mut self, graphics: &mut graphics::Graphics, atlas: &Atlas) -> Self {
+        self.set_atlas(graphics, atlas);
+        self
+    }
+
+    /// Replaces the texture with the atlas image, unless it's already in use.
+    pub fn set_atlas(&mut self, graphics: &mut graphics::Graphics, atlas: &Atlas) {
+        if atlas.is_empty() || self.atlas_id == Some(atlas.id()) {
+            return;
         }
+        self.atlas_id = Some(atlas.id());
+
+        let device = &mut graphics.device;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        let texture_view = create_texture(device, &mut encoder, atlas.image());
+        device.get_queue().submit(&[encoder.finish()]);
+        self.bind_group = create_bind_group(
+            &graphics.device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &texture_view,
+            &self.sampler,
+        );
+    }
+
+    /// Draws the instances as seen by `camera`.
+    pub fn set_camera(&mut self, graphics: &mut graphics::Graphics, camera: &Camera2d) {
+        let view_proj = camera.view_projection();
+        if view_proj == self.view_proj {
+            return;
+        }
+        self.view_proj = view_proj;
+
+        let temp_buffer = graphics.device
+            .create_buffer_mapped(1, wgpu::BufferUsage::COPY_SRC)
+            .fill_from_slice(&[GlobalUniforms { view_proj }]);
+        let mut encoder = graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        let size = std::mem::size_of::<GlobalUniforms>() as wgpu::BufferAddress;
+        encoder.copy_buffer_to_buffer(&temp_buffer, 0, &self.uniform_buffer, 0, size);
+        graphics.device.get_queue().submit(&[encoder.finish()]);
+    }
+
+    /// Sets a hard limit on the number of instances. Anything past it gets
+    /// dropped with a warning.
+    pub fn with_max_instances(mut self, max_instances: usize) -> Self {
+        self.max_instances = Some(max_instances);
+        self
     }
 
     pub fn update(&mut self, graphics: &mut graphics::Graphics, instances: &[Instance]) {
-        use std::cmp::min;
-        let instances = &instances[0..min(self.max_instances, instances.len())];
-        if instances.len() > 0 {
-            self.instance_count = instances.len();
+        let instances = match self.max_instances {
+            Some(max) if instances.len() > max => {
+                if !self.truncating {
+                    warn!("Only drawing {} of {} instances", max, instances.len());
+                    self.truncating = true;
+                }
+                &instances[0..max]
+            }
+            _ => {
+                self.truncating = false;
+                instances
+            }
+        };
+
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = grown_capacity(self.instance_capacity, instances.len(), self.max_instances);
+            info!("Growing the instance buffer to {} instances", self.instance_capacity);
+            self.instance_buffer = create_instance_buffer(&graphics.device, self.instance_capacity);
+        }
+
+        // Stale instances would still be drawn if nothing replaced them
+        self.instance_count = instances.len();
+        if !instances.is_empty() {
             let buffer_size = (instances.len() * std::mem::size_of::<Instance>()) as u64;
             let temp_buffer = graphics.device
                 .create_buffer_mapped(instances.len(), wgpu::BufferUsage::COPY_SRC)
@@ -272,7 +226,7 @@ impl QuadRenderer {
 
     pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
         if self.instance_count > 0 {
-            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_pipeline(self.pipeline.render_pipeline());
             render_pass.set_bind_group(0, &self.bind_group, &[]);
             render_pass.set_vertex_buffers(0, &[(&self.vertex_buffer, 0), (&self.instance_buffer, 0)]);
             render_pass.draw(0..self.vertex_count as u32, 0..self.instance_count as u32);
@@ -280,10 +234,241 @@ impl QuadRenderer {
     }
 }
 
+impl graphics::Renderer for QuadRenderer {
+    /// Uploads the `Vec<Instance>` resource if there is one, and switches to
+    /// the `Atlas` resource whenever it changes. Instances are drawn through
+    /// the `Camera2d` resource.
+    fn prepare(&mut self, graphics: &mut graphics::Graphics, world: &specs::World) {
+        self.pipeline.prepare(graphics);
+        if let Some(camera) = world.try_fetch::<Camera2d>() {
+            self.set_camera(graphics, &camera);
+        }
+        if let Some(atlas) = world.try_fetch::<Atlas>() {
+            self.set_atlas(graphics, &atlas);
+        }
+        if let Some(instances) = world.try_fetch::<Vec<Instance>>() {
+            self.update(graphics, &instances);
+        }
+    }
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        QuadRenderer::draw(self, render_pass);
+    }
+
+    fn reload_shaders(&mut self, graphics: &mut graphics::Graphics) {
+        self.pipeline.reload_shaders(graphics);
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    depth_stencil_state: Option<wgpu::DepthStencilStateDescriptor>,
+    sample_count: u32,
+    vs: &[u32],
+    fs: &[u32],
+) -> wgpu::RenderPipeline {
+    use std::mem;
+
+    let vs_module = device.create_shader_module(vs);
+    let fs_module = device.create_shader_module(fs);
+
+    let vb_desc = wgpu::VertexBufferDescriptor {
+        stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float2,
+                offset: 0,
+                shader_location: 0,
+            },
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float2,
+                offset: 2 * mem::size_of::<f32>() as u64,
+                shader_location: 1,
+            },
+        ],
+    };
+    let ib_desc = wgpu::VertexBufferDescriptor {
+        stride: mem::size_of::<Instance>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Instance,
+        attributes: &[
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float2,
+                offset: 0,
+                shader_location: 2,
+            },
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float2,
+                offset: mem::size_of::<cgmath::Vector2<f32>>() as u64,
+                shader_location: 3,
+            },
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float2,
+                offset: 2 * mem::size_of::<cgmath::Vector2<f32>>() as u64,
+                shader_location: 4,
+            },
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float,
+                offset: 3 * mem::size_of::<cgmath::Vector2<f32>>() as u64,
+                shader_location: 5,
+            },
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float3,
+                offset: (3 * mem::size_of::<cgmath::Vector2<f32>>() + mem::size_of::<f32>()) as u64,
+                shader_location: 6,
+            },
+            wgpu::VertexAttributeDescriptor {
+                format: wgpu::VertexFormat::Float4,
+                offset: (3 * mem::size_of::<cgmath::Vector2<f32>>() + mem::size_of::<f32>()
+                    + mem::size_of::<cgmath::Vector3<f32>>()) as u64,
+                shader_location: 7,
+            },
+        ],
+    };
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        layout,
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            depth_bias: 0,
+            depth_bias_slope_scale: 0.0,
+            depth_bias_clamp: 0.0,
+        }),
+        primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
+        color_states: &[
+            wgpu::ColorStateDescriptor {
+                format,
+                color_blend: wgpu::BlendDescriptor {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha_blend: wgpu::BlendDescriptor {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                write_mask: wgpu::ColorWrite::ALL,
+            }
+        ],
+        depth_stencil_state,
+        index_format: wgpu::IndexFormat::Uint16,
+        vertex_buffers: &[vb_desc, ib_desc],
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
+pub(super) fn create_texture(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    image: &image::RgbaImage,
+) -> wgpu::TextureView {
+    let (width, height) = image.dimensions();
+    let texture_extent = wgpu::Extent3d {
+        width,
+        height,
+        depth: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: texture_extent,
+        array_layer_count: 1,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+    });
+    let temp_buffer = device
+        .create_buffer_mapped(image.len(), wgpu::BufferUsage::COPY_SRC)
+        .fill_from_slice(image);
+    encoder.copy_buffer_to_texture(
+        wgpu::BufferCopyView {
+            buffer: &temp_buffer,
+            offset: 0,
+            row_pitch: 4 * width,
+            image_height: height,
+        },
+        wgpu::TextureCopyView {
+            texture: &texture,
+            mip_level: 0,
+            array_layer: 0,
+            origin: wgpu::Origin3d {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        },
+        texture_extent,
+    );
+    texture.create_default_view()
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    texture_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        bindings: &[
+            wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer: uniform_buffer,
+                    range: 0..64,
+                },
+            },
+            wgpu::Binding {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::Binding {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        size: (std::mem::size_of::<Instance>() * capacity.max(1)) as u64,
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+    })
+}
+
+/// The smallest capacity reachable by growing `capacity` that fits
+/// `required` instances, without going over `max`.
+pub(super) fn grown_capacity(capacity: usize, required: usize, max: Option<usize>) -> usize {
+    let mut grown = capacity.max(1);
+    while grown < required {
+        grown *= GROWTH_FACTOR;
+    }
+    match max {
+        Some(max) => grown.min(max),
+        None => grown,
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct GlobalUniforms {
-    proj: cgmath::Matrix4<f32>,
+    view_proj: cgmath::Matrix4<f32>,
 }
 
 #[repr(C)]
@@ -294,6 +479,24 @@ pub struct Instance {
     pub scale: cgmath::Vector2<f32>,
     pub rotation: f32,
     pub color: cgmath::Vector3<f32>,
+    /// The part of the texture to draw, usually from an `Atlas`.
+    pub uv: UvRect,
+}
+
+impl Instance {
+    /// The smallest rectangle containing the whole quad, after it's been
+    /// scaled and rotated.
+    pub fn bounds(&self) -> Bounds {
+        let corners = instance_corners(self);
+        let mut bounds = Bounds { min: corners[0], max: corners[0] };
+        for corner in &corners[1..] {
+            bounds.min.x = bounds.min.x.min(corner.x);
+            bounds.min.y = bounds.min.y.min(corner.y);
+            bounds.max.x = bounds.max.x.max(corner.x);
+            bounds.max.y = bounds.max.y.max(corner.y);
+        }
+        bounds
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -314,4 +517,44 @@ fn create_quad() -> Vec<Vertex> {
         vertex([0.5, 0.5], [1.0, 1.0]),
     ];
     vertex_data.to_vec()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::MetricSpace;
+
+    #[test]
+    fn capacity_grows_by_growth_factor() {
+        assert_eq!(grown_capacity(100, 101, None), 200);
+        assert_eq!(grown_capacity(100, 350, None), 400);
+    }
+
+    #[test]
+    fn capacity_grows_from_zero() {
+        assert_eq!(grown_capacity(0, 3, None), 4);
+    }
+
+    #[test]
+    fn capacity_is_capped() {
+        assert_eq!(grown_capacity(100, 150, Some(120)), 120);
+    }
+
+    #[test]
+    fn bounds_contain_the_rotated_quad() {
+        let instance = Instance {
+            offset: (1.0, 2.0).into(),
+            origin: (0.0, 0.0).into(),
+            scale: (2.0, 2.0).into(),
+            rotation: std::f32::consts::FRAC_PI_4,
+            color: (1.0, 1.0, 1.0).into(),
+            uv: UvRect::FULL,
+        };
+        let bounds = instance.bounds();
+        let half_diagonal = 2.0_f32.sqrt();
+        let expected_min = cgmath::Vector2::new(1.0 - half_diagonal, 2.0 - half_diagonal);
+        let expected_max = cgmath::Vector2::new(1.0 + half_diagonal, 2.0 + half_diagonal);
+        assert!(bounds.min.distance(expected_min) < 1e-5, "{:?}", bounds);
+        assert!(bounds.max.distance(expected_max) < 1e-5, "{:?}", bounds);
+    }
+}
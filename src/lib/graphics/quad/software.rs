@@ -0,0 +1,268 @@
+//! A cpu implementation of `shader.vert` and `shader.frag`, for checking
+//! sprite layout without a gpu. Like the gpu pipeline, blending happens in
+//! linear space and the result is encoded as srgb.
+
+use super::Instance;
+use cgmath::{ElementWise, Matrix4, SquareMatrix, Vector2};
+
+/// The corners of the quad for `instance` in world space, in the same order
+/// as the vertices from `create_quad`. With the default camera, world space
+/// is clip space.
+pub fn instance_corners(instance: &Instance) -> [Vector2<f32>; 4] {
+    let corner = |x: f32, y: f32| transform(instance, Vector2::new(x, y));
+    [
+        corner(-0.5, -0.5),
+        corner(0.5, -0.5),
+        corner(-0.5, 0.5),
+        corner(0.5, 0.5),
+    ]
+}
+
+/// Draws `instances` in order on top of `clear`, which is a linear color.
+/// The texture is sampled with nearest filtering.
+pub fn rasterize_quads(
+    instances: &[Instance],
+    texture: &image::RgbaImage,
+    width: u32,
+    height: u32,
+    clear: [f32; 4],
+) -> image::RgbaImage {
+    rasterize_quads_with_camera(instances, Matrix4::identity(), texture, width, height, clear)
+}
+
+/// Like `rasterize_quads`, but seen through a camera's view projection.
+pub fn rasterize_quads_with_camera(
+    instances: &[Instance],
+    view_proj: Matrix4<f32>,
+    texture: &image::RgbaImage,
+    width: u32,
+    height: u32,
+    clear: [f32; 4],
+) -> image::RgbaImage {
+    let mut target = vec![clear; (width * height) as usize];
+    let clip_to_world = match view_proj.invert() {
+        Some(clip_to_world) => clip_to_world,
+        // A camera with zero zoom sees nothing
+        None => return to_image(&target, width, height),
+    };
+
+    for instance in instances {
+        if instance.scale.x == 0.0 || instance.scale.y == 0.0 {
+            continue;
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let clip = Vector2::new(
+                    (x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+                    1.0 - (y as f32 + 0.5) / height as f32 * 2.0,
+                );
+                let world = clip_to_world * clip.extend(0.0).extend(1.0);
+                let local = inverse_transform(instance, world.truncate().truncate());
+                if local.x < -0.5 || local.x >= 0.5 || local.y < -0.5 || local.y >= 0.5 {
+                    continue;
+                }
+
+                let tex_coord = (local + Vector2::new(0.5, 0.5)).mul_element_wise(instance.uv.size) + instance.uv.offset;
+                let tex = sample_nearest(texture, tex_coord);
+                let src = [
+                    tex[0] * instance.color.x,
+                    tex[1] * instance.color.y,
+                    tex[2] * instance.color.z,
+                    tex[3],
+                ];
+
+                let dst = &mut target[(y * width + x) as usize];
+                let alpha = src[3];
+                for i in 0..4 {
+                    dst[i] = src[i] * alpha + dst[i] * (1.0 - alpha);
+                }
+            }
+        }
+    }
+
+    to_image(&target, width, height)
+}
+
+/// Encodes linear colors as an srgb image.
+fn to_image(target: &[[f32; 4]], width: u32, height: u32) -> image::RgbaImage {
+    image::RgbaImage::from_fn(width, height, |x, y| {
+        let c = target[(y * width + x) as usize];
+        image::Rgba([
+            to_byte(linear_to_srgb(c[0])),
+            to_byte(linear_to_srgb(c[1])),
+            to_byte(linear_to_srgb(c[2])),
+            to_byte(c[3]),
+        ])
+    })
+}
+
+fn transform(instance: &Instance, pos: Vector2<f32>) -> Vector2<f32> {
+    let pos = (pos - instance.origin).mul_element_wise(instance.scale);
+    let (sin, cos) = instance.rotation.sin_cos();
+    let pos = Vector2::new(
+        pos.x * cos + pos.y * sin,
+        -pos.x * sin + pos.y * cos,
+    );
+    pos + instance.offset
+}
+
+fn inverse_transform(instance: &Instance, world: Vector2<f32>) -> Vector2<f32> {
+    let pos = world - instance.offset;
+    let (sin, cos) = instance.rotation.sin_cos();
+    let pos = Vector2::new(
+        pos.x * cos - pos.y * sin,
+        pos.x * sin + pos.y * cos,
+    );
+    pos.div_element_wise(instance.scale) + instance.origin
+}
+
+fn sample_nearest(texture: &image::RgbaImage, tex_coord: Vector2<f32>) -> [f32; 4] {
+    let (width, height) = texture.dimensions();
+    let x = ((tex_coord.x * width as f32) as u32).min(width - 1);
+    let y = ((tex_coord.y * height as f32) as u32).min(height - 1);
+    let texel = texture.get_pixel(x, y).0;
+    [
+        srgb_to_linear(texel[0] as f32 / 255.0),
+        srgb_to_linear(texel[1] as f32 / 255.0),
+        srgb_to_linear(texel[2] as f32 / 255.0),
+        texel[3] as f32 / 255.0,
+    ]
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_byte(c: f32) -> u8 {
+    (c.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::{camera::Camera2d, graphics::UvRect};
+    use cgmath::Zero;
+
+    const CLEAR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    fn white() -> image::RgbaImage {
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]))
+    }
+
+    fn instance(offset: (f32, f32), scale: (f32, f32), rotation: f32, color: (f32, f32, f32)) -> Instance {
+        Instance {
+            offset: offset.into(),
+            origin: Vector2::zero(),
+            scale: scale.into(),
+            rotation,
+            color: color.into(),
+            uv: UvRect::FULL,
+        }
+    }
+
+    fn assert_close(a: Vector2<f32>, b: Vector2<f32>) {
+        assert!((a - b).x.abs() < 1e-5 && (a - b).y.abs() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn no_instances_is_clear_color() {
+        let image = rasterize_quads(&[], &white(), 8, 8, [1.0, 0.0, 0.0, 1.0]);
+        assert!(image.pixels().all(|p| p.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn corners_are_scaled_and_offset() {
+        let corners = instance_corners(&instance((0.25, -0.5), (2.0, 1.0), 0.0, (1.0, 1.0, 1.0)));
+        assert_close(corners[0], Vector2::new(-0.75, -1.0));
+        assert_close(corners[3], Vector2::new(1.25, 0.0));
+    }
+
+    #[test]
+    fn corners_rotate_clockwise() {
+        let corners = instance_corners(&instance((0.0, 0.0), (1.0, 1.0), std::f32::consts::FRAC_PI_2, (1.0, 1.0, 1.0)));
+        assert_close(corners[1], Vector2::new(-0.5, -0.5));
+        assert_close(corners[3], Vector2::new(0.5, -0.5));
+    }
+
+    #[test]
+    fn quad_covers_its_bounds() {
+        let image = rasterize_quads(&[instance((0.0, 0.0), (1.0, 1.0), 0.0, (1.0, 1.0, 1.0))], &white(), 8, 8, CLEAR);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(2, 2).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(5, 5).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(6, 6).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rotation_swaps_width_and_height() {
+        let tall = instance((0.0, 0.0), (0.5, 2.0), std::f32::consts::FRAC_PI_2, (1.0, 1.0, 1.0));
+        let image = rasterize_quads(&[tall], &white(), 8, 8, CLEAR);
+        assert_eq!(image.get_pixel(0, 4).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(4, 0).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn color_modulates_texture() {
+        let image = rasterize_quads(&[instance((0.0, 0.0), (2.0, 2.0), 0.0, (1.0, 0.0, 0.0))], &white(), 4, 4, CLEAR);
+        assert!(image.pixels().all(|p| p.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn later_instances_draw_on_top() {
+        let instances = [
+            instance((0.0, 0.0), (2.0, 2.0), 0.0, (1.0, 0.0, 0.0)),
+            instance((0.0, 0.0), (2.0, 2.0), 0.0, (0.0, 0.0, 1.0)),
+        ];
+        let image = rasterize_quads(&instances, &white(), 4, 4, CLEAR);
+        assert!(image.pixels().all(|p| p.0 == [0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn transparent_texels_blend_with_target() {
+        let texture = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 0]));
+        let image = rasterize_quads(&[instance((0.0, 0.0), (2.0, 2.0), 0.0, (1.0, 1.0, 1.0))], &texture, 4, 4, CLEAR);
+        assert!(image.pixels().all(|p| p.0[..3] == [0, 0, 0]));
+    }
+
+    #[test]
+    fn camera_moves_the_view() {
+        let camera = Camera2d {
+            position: (1.0, 0.0).into(),
+            zoom: 2.0,
+            ..Camera2d::default()
+        };
+        let quad = instance((1.0, 0.0), (0.5, 0.5), 0.0, (1.0, 1.0, 1.0));
+        let image = rasterize_quads_with_camera(&[quad], camera.view_projection(), &white(), 8, 8, CLEAR);
+        // The quad is in the middle of the view, and twice as big
+        assert_eq!(image.get_pixel(2, 2).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(5, 5).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(1, 1).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(6, 6).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn uv_rect_selects_part_of_texture() {
+        let mut texture = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 255]));
+        texture.put_pixel(1, 0, image::Rgba([0, 255, 0, 255]));
+        let mut right_half = instance((0.0, 0.0), (2.0, 2.0), 0.0, (1.0, 1.0, 1.0));
+        right_half.uv = UvRect {
+            offset: (0.5, 0.0).into(),
+            size: (0.5, 1.0).into(),
+        };
+        let image = rasterize_quads(&[right_half], &texture, 4, 4, CLEAR);
+        assert!(image.pixels().all(|p| p.0 == [0, 255, 0, 255]));
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::{Arc, Mutex};
+
+pub enum RenderTarget {
+    Window {
+        surface: wgpu::Surface,
+        swap_chain: wgpu::SwapChain,
+    },
+    Offscreen(Offscreen),
+}
+
+impl RenderTarget {
+    pub fn next_texture(&mut self) -> FrameTexture {
+        match self {
+            RenderTarget::Window { swap_chain, .. } => FrameTexture::SwapChain(swap_chain.get_next_texture()),
+            RenderTarget::Offscreen(offscreen) => FrameTexture::Offscreen(&offscreen.view),
+        }
+    }
+
+    pub(super) fn recreate(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        match self {
+            RenderTarget::Window { surface, swap_chain } => {
+                *swap_chain = device.create_swap_chain(surface, sc_desc);
+            }
+            RenderTarget::Offscreen(offscreen) => {
+                *offscreen = Offscreen::new(device, sc_desc);
+            }
+        }
+    }
+}
+
+/// The texture a frame gets drawn into. Swap chain textures are presented
+/// when this is dropped.
+pub enum FrameTexture<'a> {
+    SwapChain(wgpu::SwapChainOutput<'a>),
+    Offscreen(&'a wgpu::TextureView),
+}
+
+impl<'a> FrameTexture<'a> {
+    pub fn view(&self) -> &wgpu::TextureView {
+        match self {
+            FrameTexture::SwapChain(output) => &output.view,
+            FrameTexture::Offscreen(view) => view,
+        }
+    }
+}
+
+pub struct Offscreen {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl Offscreen {
+    pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: sc_desc.format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let view = texture.create_default_view();
+        Self { texture, view }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Whether `read` can turn textures of this format into an image. Only
+    /// 8-bit rgba and bgra formats can.
+    pub fn supports_format(format: wgpu::TextureFormat) -> bool {
+        match format {
+            wgpu::TextureFormat::Rgba8Unorm
+            | wgpu::TextureFormat::Rgba8UnormSrgb
+            | wgpu::TextureFormat::Bgra8Unorm
+            | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => false,
+        }
+    }
+
+    /// Copies the texture back to the cpu. This blocks until the gpu has
+    /// finished all the work submitted so far.
+    pub fn read(&self, device: &mut wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> image::RgbaImage {
+        let (width, height) = (sc_desc.width, sc_desc.height);
+        let bytes_per_row = 4 * width;
+        let row_pitch = align_to(bytes_per_row, ROW_PITCH_ALIGNMENT);
+        let buffer_size = (row_pitch * height) as wgpu::BufferAddress;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: buffer_size,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                array_layer: 0,
+                origin: wgpu::Origin3d {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+            },
+            wgpu::BufferCopyView {
+                buffer: &buffer,
+                offset: 0,
+                row_pitch,
+                image_height: height,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        device.get_queue().submit(&[encoder.finish()]);
+
+        let mapped = Arc::new(Mutex::new(None));
+        let result = mapped.clone();
+        buffer.map_read_async(0, buffer_size, move |mapping: wgpu::BufferMapAsyncResult<&[u8]>| {
+            if let Ok(mapping) = mapping {
+                *result.lock().unwrap() = Some(mapping.data.to_vec());
+            }
+        });
+        device.poll(true);
+
+        let data = mapped.lock().unwrap().take().expect("Unable to map the readback buffer");
+        let swizzle = match sc_desc.format {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => false,
+        };
+
+        let mut pixels = Vec::with_capacity((bytes_per_row * height) as usize);
+        for row in data.chunks(row_pitch as usize) {
+            let row = &row[..bytes_per_row as usize];
+            if swizzle {
+                for bgra in row.chunks(4) {
+                    pixels.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+                }
+            } else {
+                pixels.extend_from_slice(row);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels).unwrap()
+    }
+}
+
+/// Buffer rows used in texture copies must be a multiple of this many bytes.
+const ROW_PITCH_ALIGNMENT: u32 = 256;
+
+fn align_to(value: u32, alignment: u32) -> u32 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_8_bit_color_formats_are_readable() {
+        assert!(Offscreen::supports_format(wgpu::TextureFormat::Rgba8UnormSrgb));
+        assert!(Offscreen::supports_format(wgpu::TextureFormat::Bgra8Unorm));
+        assert!(!Offscreen::supports_format(wgpu::TextureFormat::Rgba16Float));
+        assert!(!Offscreen::supports_format(wgpu::TextureFormat::R8Unorm));
+    }
+}
@@ -1,18 +1,27 @@
-use crate::lib::util;
+use crate::lib::{
+    graphics::{DepthTest, ShaderPipeline},
+    shader::{ShaderError, ShaderSource},
+    util,
+};
+
+const VERTEX_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/triangle/shader.vert"),
+    include_str!("shader.vert"),
+    util::ShaderStage::Vertex,
+);
+const FRAGMENT_SHADER: ShaderSource = ShaderSource::new(
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/graphics/triangle/shader.frag"),
+    include_str!("shader.frag"),
+    util::ShaderStage::Fragment,
+);
 
 pub struct TriangleRenderer {
     bind_group: wgpu::BindGroup,
-    pub render_pipeline: wgpu::RenderPipeline,
+    pipeline: ShaderPipeline,
 }
 
 impl TriangleRenderer {
-    pub fn new(graphics: &super::Graphics) -> Self {
-        let vs = util::load_glsl(include_str!("shader.vert"), util::ShaderStage::Vertex);
-        let fs = util::load_glsl(include_str!("shader.frag"), util::ShaderStage::Fragment);
-
-        let vs_module = graphics.device.create_shader_module(&vs);
-        let fs_module = graphics.device.create_shader_module(&fs);
-
+    pub fn new(graphics: &mut super::Graphics) -> Result<Self, ShaderError> {
         let bind_group_layout = graphics.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             bindings: &[],
         });
@@ -24,44 +33,83 @@ impl TriangleRenderer {
         let pipeline_layout = graphics.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             bind_group_layouts: &[&bind_group_layout],
         });
-        let render_pipeline = graphics.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            layout: &pipeline_layout,
-            vertex_stage: wgpu::ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: "main",
-            },
-            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: "main",
-            }),
-            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::None,
-                depth_bias: 0,
-                depth_bias_slope_scale: 0.0,
-                depth_bias_clamp: 0.0,
-            }),
-            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-            color_states: &[wgpu::ColorStateDescriptor {
-                format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                color_blend: wgpu::BlendDescriptor::REPLACE,
-                alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                write_mask: wgpu::ColorWrite::ALL,
-            }],
-            depth_stencil_state: None,
-            index_format: wgpu::IndexFormat::Uint16,
-            vertex_buffers: &[],
-            sample_count: 1,
-            sample_mask: !0,
-            alpha_to_coverage_enabled: false,
-        });
+        let pipeline = ShaderPipeline::new(
+            graphics,
+            pipeline_layout,
+            DepthTest::Disabled,
+            VERTEX_SHADER,
+            FRAGMENT_SHADER,
+            create_render_pipeline,
+        )?;
 
-        Self { bind_group, render_pipeline }
+        Ok(Self {
+            bind_group,
+            pipeline,
+        })
     }
 
     pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
-        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_pipeline(self.pipeline.render_pipeline());
         render_pass.set_bind_group(0, &self.bind_group, &[]);
         render_pass.draw(0..3, 0..1);
     }
-}
\ No newline at end of file
+}
+
+impl super::Renderer for TriangleRenderer {
+    fn prepare(&mut self, graphics: &mut super::Graphics, _world: &specs::World) {
+        self.pipeline.prepare(graphics);
+    }
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        TriangleRenderer::draw(self, render_pass);
+    }
+
+    fn reload_shaders(&mut self, graphics: &mut super::Graphics) {
+        self.pipeline.reload_shaders(graphics);
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    depth_stencil_state: Option<wgpu::DepthStencilStateDescriptor>,
+    sample_count: u32,
+    vs: &[u32],
+    fs: &[u32],
+) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(vs);
+    let fs_module = device.create_shader_module(fs);
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        layout,
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            depth_bias: 0,
+            depth_bias_slope_scale: 0.0,
+            depth_bias_clamp: 0.0,
+        }),
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        color_states: &[wgpu::ColorStateDescriptor {
+            format,
+            color_blend: wgpu::BlendDescriptor::REPLACE,
+            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        depth_stencil_state,
+        index_format: wgpu::IndexFormat::Uint16,
+        vertex_buffers: &[],
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
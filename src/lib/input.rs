@@ -0,0 +1,467 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use cgmath::Zero;
+use specs::{prelude::*, shrev::EventChannel};
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+/// Scroll deltas reported in pixels get converted to lines using this.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Published through an `EventChannel<InputEvent>` in the world whenever the
+/// input state changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    KeyPressed(VirtualKeyCode),
+    KeyReleased(VirtualKeyCode),
+    MouseButtonPressed(MouseButton),
+    MouseButtonReleased(MouseButton),
+    CursorMoved(cgmath::Vector2<f32>),
+    MouseMoved(cgmath::Vector2<f32>),
+    Scrolled(cgmath::Vector2<f32>),
+}
+
+#[derive(Debug)]
+struct ButtonState<T: Hash + Eq> {
+    down: HashSet<T>,
+    just_pressed: HashSet<T>,
+    just_released: HashSet<T>,
+}
+
+impl<T: Hash + Eq + Copy> ButtonState<T> {
+    fn press(&mut self, button: T) -> bool {
+        let changed = self.down.insert(button);
+        if changed {
+            self.just_pressed.insert(button);
+        }
+        changed
+    }
+
+    fn release(&mut self, button: T) -> bool {
+        let changed = self.down.remove(&button);
+        if changed {
+            self.just_released.insert(button);
+        }
+        changed
+    }
+
+    fn end_tick(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+impl<T: Hash + Eq> Default for ButtonState<T> {
+    fn default() -> Self {
+        Self {
+            down: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
+    }
+}
+
+/// The keyboard and mouse state as seen by the systems. The "just" states
+/// and deltas cover everything that happened since the last fixed timestep
+/// tick, so `end_tick` needs to be called after every dispatch. Systems that
+/// run every frame use the frame deltas instead, which `end_frame` clears.
+#[derive(Debug)]
+pub struct InputState {
+    keys: ButtonState<VirtualKeyCode>,
+    mouse_buttons: ButtonState<MouseButton>,
+    modifiers: ModifiersState,
+    cursor_position: cgmath::Vector2<f32>,
+    mouse_delta: cgmath::Vector2<f32>,
+    scroll_delta: cgmath::Vector2<f32>,
+    frame_mouse_delta: cgmath::Vector2<f32>,
+    frame_scroll_delta: cgmath::Vector2<f32>,
+    hidpi_factor: f64,
+    focused: bool,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            keys: ButtonState::default(),
+            mouse_buttons: ButtonState::default(),
+            modifiers: ModifiersState::default(),
+            cursor_position: cgmath::Vector2::zero(),
+            mouse_delta: cgmath::Vector2::zero(),
+            scroll_delta: cgmath::Vector2::zero(),
+            frame_mouse_delta: cgmath::Vector2::zero(),
+            frame_scroll_delta: cgmath::Vector2::zero(),
+            hidpi_factor: 1.0,
+            focused: true,
+        }
+    }
+}
+
+impl InputState {
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys.down.contains(&key)
+    }
+
+    pub fn is_key_just_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys.just_pressed.contains(&key)
+    }
+
+    pub fn is_key_just_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys.just_released.contains(&key)
+    }
+
+    pub fn is_mouse_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.down.contains(&button)
+    }
+
+    pub fn is_mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.just_pressed.contains(&button)
+    }
+
+    pub fn is_mouse_just_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.just_released.contains(&button)
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    /// The cursor position in physical pixels from the top left of the
+    /// window, which is what `Camera2d::screen_to_world` expects.
+    pub fn cursor_position(&self) -> cgmath::Vector2<f32> {
+        self.cursor_position
+    }
+
+    /// How far the mouse moved this tick. Unlike the cursor, this keeps
+    /// changing when the cursor hits the edge of the screen, so it's what
+    /// mouse look should use.
+    pub fn mouse_delta(&self) -> cgmath::Vector2<f32> {
+        self.mouse_delta
+    }
+
+    /// How far the mouse wheel scrolled this tick, in lines.
+    pub fn scroll_delta(&self) -> cgmath::Vector2<f32> {
+        self.scroll_delta
+    }
+
+    /// How far the mouse moved this frame.
+    pub fn frame_mouse_delta(&self) -> cgmath::Vector2<f32> {
+        self.frame_mouse_delta
+    }
+
+    /// How far the mouse wheel scrolled this frame, in lines.
+    pub fn frame_scroll_delta(&self) -> cgmath::Vector2<f32> {
+        self.frame_scroll_delta
+    }
+
+    /// Whether the window has focus. Mouse motion is ignored while it
+    /// doesn't, as it comes from the device rather than the window.
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// How many physical pixels there are per logical pixel. Window events
+    /// keep this up to date, but it needs to start out as the window's.
+    pub fn set_hidpi_factor(&mut self, hidpi_factor: f64) {
+        self.hidpi_factor = hidpi_factor;
+    }
+
+    pub fn press_key(&mut self, key: VirtualKeyCode) -> Option<InputEvent> {
+        if self.keys.press(key) { Some(InputEvent::KeyPressed(key)) } else { None }
+    }
+
+    pub fn release_key(&mut self, key: VirtualKeyCode) -> Option<InputEvent> {
+        if self.keys.release(key) { Some(InputEvent::KeyReleased(key)) } else { None }
+    }
+
+    pub fn press_mouse(&mut self, button: MouseButton) -> Option<InputEvent> {
+        if self.mouse_buttons.press(button) { Some(InputEvent::MouseButtonPressed(button)) } else { None }
+    }
+
+    pub fn release_mouse(&mut self, button: MouseButton) -> Option<InputEvent> {
+        if self.mouse_buttons.release(button) { Some(InputEvent::MouseButtonReleased(button)) } else { None }
+    }
+
+    pub fn move_cursor(&mut self, position: cgmath::Vector2<f32>) -> Option<InputEvent> {
+        self.cursor_position = position;
+        Some(InputEvent::CursorMoved(position))
+    }
+
+    pub fn move_mouse(&mut self, delta: cgmath::Vector2<f32>) -> Option<InputEvent> {
+        self.mouse_delta += delta;
+        self.frame_mouse_delta += delta;
+        Some(InputEvent::MouseMoved(delta))
+    }
+
+    pub fn scroll(&mut self, delta: cgmath::Vector2<f32>) -> Option<InputEvent> {
+        self.scroll_delta += delta;
+        self.frame_scroll_delta += delta;
+        Some(InputEvent::Scrolled(delta))
+    }
+
+    /// Releases every key and mouse button that's down, returning a release
+    /// event for each.
+    pub fn release_all(&mut self) -> Vec<InputEvent> {
+        let keys: Vec<_> = self.keys.down.iter().cloned().collect();
+        let buttons: Vec<_> = self.mouse_buttons.down.iter().cloned().collect();
+        let mut events: Vec<InputEvent> = keys.into_iter().filter_map(|key| self.release_key(key)).collect();
+        events.extend(buttons.into_iter().filter_map(|button| self.release_mouse(button)));
+        events
+    }
+
+    /// Updates the state from a window event, returning the `InputEvent`s it
+    /// caused. Repeated key presses from holding a key down are ignored.
+    pub fn handle_window_event(&mut self, event: &WindowEvent) -> Vec<InputEvent> {
+        match *event {
+            // We won't hear about keys released while unfocused
+            WindowEvent::Focused(false) => {
+                self.focused = false;
+                self.release_all()
+            }
+            WindowEvent::Focused(true) => {
+                self.focused = true;
+                Vec::new()
+            }
+            _ => self.handle_input_event(event).into_iter().collect(),
+        }
+    }
+
+    fn handle_input_event(&mut self, event: &WindowEvent) -> Option<InputEvent> {
+        match *event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { virtual_keycode, state, modifiers, .. },
+                ..
+            } => {
+                self.modifiers = modifiers;
+                match (virtual_keycode, state) {
+                    (Some(key), ElementState::Pressed) => self.press_key(key),
+                    (Some(key), ElementState::Released) => self.release_key(key),
+                    (None, _) => None,
+                }
+            }
+            WindowEvent::MouseInput { state, button, modifiers, .. } => {
+                self.modifiers = modifiers;
+                match state {
+                    ElementState::Pressed => self.press_mouse(button),
+                    ElementState::Released => self.release_mouse(button),
+                }
+            }
+            WindowEvent::CursorMoved { position, modifiers, .. } => {
+                self.modifiers = modifiers;
+                let position = position.to_physical(self.hidpi_factor);
+                self.move_cursor((position.x as f32, position.y as f32).into())
+            }
+            WindowEvent::MouseWheel { delta, modifiers, .. } => {
+                self.modifiers = modifiers;
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (x, y).into(),
+                    MouseScrollDelta::PixelDelta(pixels) => cgmath::Vector2::new(
+                        pixels.x as f32 / PIXELS_PER_LINE,
+                        pixels.y as f32 / PIXELS_PER_LINE,
+                    ),
+                };
+                self.scroll(delta)
+            }
+            WindowEvent::HiDpiFactorChanged(hidpi_factor) => {
+                self.hidpi_factor = hidpi_factor;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Updates the state from raw device input, which is where mouse
+    /// movement comes from. Devices report to every window, so this is
+    /// ignored while the window doesn't have focus.
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) -> Option<InputEvent> {
+        match *event {
+            DeviceEvent::MouseMotion { .. } if !self.focused => None,
+            DeviceEvent::MouseMotion { delta: (x, y) } => self.move_mouse((x as f32, y as f32).into()),
+            _ => None,
+        }
+    }
+
+    /// Clears the per tick state. Call this after each fixed timestep update.
+    pub fn end_tick(&mut self) {
+        self.keys.end_tick();
+        self.mouse_buttons.end_tick();
+        self.mouse_delta = cgmath::Vector2::zero();
+        self.scroll_delta = cgmath::Vector2::zero();
+    }
+
+    /// Clears the per frame state. Call this after drawing each frame.
+    pub fn end_frame(&mut self) {
+        self.frame_mouse_delta = cgmath::Vector2::zero();
+        self.frame_scroll_delta = cgmath::Vector2::zero();
+    }
+}
+
+/// Passes a window event to the `InputState` in `world`, and publishes the
+/// resulting `InputEvent`s to the world's `EventChannel<InputEvent>`.
+pub fn handle_window_event(world: &World, event: &WindowEvent) {
+    let mut input_events = world.write_resource::<InputState>().handle_window_event(event);
+    if !input_events.is_empty() {
+        world.write_resource::<EventChannel<InputEvent>>().drain_vec_write(&mut input_events);
+    }
+}
+
+/// Like `handle_window_event`, but for device events.
+pub fn handle_device_event(world: &World, event: &DeviceEvent) {
+    let input_event = world.write_resource::<InputState>().handle_device_event(event);
+    if let Some(input_event) = input_event {
+        world.write_resource::<EventChannel<InputEvent>>().single_write(input_event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_sets_down_and_just_pressed() {
+        let mut input = InputState::default();
+        assert_eq!(input.press_key(VirtualKeyCode::A), Some(InputEvent::KeyPressed(VirtualKeyCode::A)));
+        assert!(input.is_key_down(VirtualKeyCode::A));
+        assert!(input.is_key_just_pressed(VirtualKeyCode::A));
+        assert!(!input.is_key_just_released(VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn end_tick_clears_just_states() {
+        let mut input = InputState::default();
+        input.press_key(VirtualKeyCode::A);
+        input.end_tick();
+        assert!(input.is_key_down(VirtualKeyCode::A));
+        assert!(!input.is_key_just_pressed(VirtualKeyCode::A));
+
+        input.release_key(VirtualKeyCode::A);
+        assert!(!input.is_key_down(VirtualKeyCode::A));
+        assert!(input.is_key_just_released(VirtualKeyCode::A));
+        input.end_tick();
+        assert!(!input.is_key_just_released(VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn tap_within_a_tick_is_pressed_and_released() {
+        let mut input = InputState::default();
+        input.press_mouse(MouseButton::Left);
+        input.release_mouse(MouseButton::Left);
+        assert!(!input.is_mouse_down(MouseButton::Left));
+        assert!(input.is_mouse_just_pressed(MouseButton::Left));
+        assert!(input.is_mouse_just_released(MouseButton::Left));
+    }
+
+    #[test]
+    fn repeated_press_is_ignored() {
+        let mut input = InputState::default();
+        input.press_key(VirtualKeyCode::Space);
+        input.end_tick();
+        assert_eq!(input.press_key(VirtualKeyCode::Space), None);
+        assert!(!input.is_key_just_pressed(VirtualKeyCode::Space));
+    }
+
+    #[test]
+    fn scroll_accumulates_until_end_tick() {
+        let mut input = InputState::default();
+        input.scroll((0.0, 1.0).into());
+        input.scroll((0.0, 2.0).into());
+        assert_eq!(input.scroll_delta(), (0.0, 3.0).into());
+        input.end_tick();
+        assert_eq!(input.scroll_delta(), (0.0, 0.0).into());
+    }
+
+    #[test]
+    fn mouse_motion_accumulates_until_end_tick() {
+        let mut input = InputState::default();
+        let event = input.handle_device_event(&DeviceEvent::MouseMotion { delta: (3.0, -1.0) });
+        assert_eq!(event, Some(InputEvent::MouseMoved((3.0, -1.0).into())));
+        input.move_mouse((1.0, 1.0).into());
+        assert_eq!(input.mouse_delta(), (4.0, 0.0).into());
+        input.end_tick();
+        assert_eq!(input.mouse_delta(), (0.0, 0.0).into());
+    }
+
+    #[test]
+    fn frame_deltas_outlast_ticks() {
+        let mut input = InputState::default();
+        input.move_mouse((2.0, 1.0).into());
+        input.scroll((0.0, 1.0).into());
+        input.end_tick();
+        assert_eq!(input.mouse_delta(), (0.0, 0.0).into());
+        assert_eq!(input.frame_mouse_delta(), (2.0, 1.0).into());
+        assert_eq!(input.frame_scroll_delta(), (0.0, 1.0).into());
+        input.end_frame();
+        assert_eq!(input.frame_mouse_delta(), (0.0, 0.0).into());
+        assert_eq!(input.frame_scroll_delta(), (0.0, 0.0).into());
+    }
+
+    #[test]
+    fn mouse_motion_is_ignored_while_unfocused() {
+        let mut input = InputState::default();
+        input.handle_window_event(&WindowEvent::Focused(false));
+        assert!(!input.is_focused());
+        assert_eq!(input.handle_device_event(&DeviceEvent::MouseMotion { delta: (3.0, -1.0) }), None);
+        assert_eq!(input.mouse_delta(), (0.0, 0.0).into());
+
+        input.handle_window_event(&WindowEvent::Focused(true));
+        assert!(input.handle_device_event(&DeviceEvent::MouseMotion { delta: (3.0, -1.0) }).is_some());
+        assert_eq!(input.mouse_delta(), (3.0, -1.0).into());
+    }
+
+    #[test]
+    fn cursor_is_in_physical_pixels() {
+        let mut input = InputState::default();
+        input.set_hidpi_factor(2.0);
+        let event = input.handle_window_event(&WindowEvent::CursorMoved {
+            device_id: unsafe { winit::event::DeviceId::dummy() },
+            position: winit::dpi::LogicalPosition::new(100.0, 50.0),
+            modifiers: ModifiersState::default(),
+        });
+        assert_eq!(event, vec![InputEvent::CursorMoved((200.0, 100.0).into())]);
+
+        // The camera's viewport is the swap chain size, which is physical
+        let mut camera = crate::lib::camera::Camera2d::default();
+        camera.set_viewport(400.0, 200.0);
+        let center = camera.screen_to_world(input.cursor_position());
+        assert!(center.x.abs() < 1e-5 && center.y.abs() < 1e-5);
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut input = InputState::default();
+        input.press_key(VirtualKeyCode::W);
+        input.press_mouse(MouseButton::Right);
+        input.end_tick();
+
+        let mut events = input.handle_window_event(&WindowEvent::Focused(false));
+        events.sort_by_key(|event| format!("{:?}", event));
+        assert_eq!(events, vec![
+            InputEvent::KeyReleased(VirtualKeyCode::W),
+            InputEvent::MouseButtonReleased(MouseButton::Right),
+        ]);
+        assert!(!input.is_key_down(VirtualKeyCode::W));
+        assert!(input.is_mouse_just_released(MouseButton::Right));
+        assert!(input.handle_window_event(&WindowEvent::Focused(false)).is_empty());
+    }
+
+    #[test]
+    fn focus_loss_releases_reach_the_event_channel() {
+        let mut world = World::new();
+        world.insert(InputState::default());
+        world.insert(EventChannel::<InputEvent>::new());
+        let mut reader = world.write_resource::<EventChannel<InputEvent>>().register_reader();
+        world.write_resource::<InputState>().press_key(VirtualKeyCode::A);
+
+        handle_window_event(&world, &WindowEvent::Focused(false));
+
+        let channel = world.read_resource::<EventChannel<InputEvent>>();
+        let events: Vec<_> = channel.read(&mut reader).cloned().collect();
+        assert_eq!(events, vec![InputEvent::KeyReleased(VirtualKeyCode::A)]);
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+
+use super::{compute_normals, decode_data_uri, decode_texture, read_relative, Material, Mesh, MeshError, MeshVertex, Submesh};
+
+pub(super) fn load(bytes: &[u8], path: &Path) -> Result<Mesh, MeshError> {
+    let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice(bytes).map_err(MeshError::Gltf)?;
+
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            ::gltf::buffer::Source::Bin => blob.clone()
+                .ok_or_else(|| MeshError::Invalid("the binary chunk is missing".to_string()))?,
+            ::gltf::buffer::Source::Uri(uri) => match decode_data_uri(uri) {
+                Some(data) => data?,
+                None => read_relative(path, uri)?,
+            },
+        };
+        if data.len() < buffer.length() {
+            return Err(MeshError::Invalid(format!("buffer {} is too short", buffer.index())));
+        }
+        buffers.push(data);
+    }
+
+    let mut textures = Vec::new();
+    for image in document.images() {
+        let texture = match image.source() {
+            ::gltf::image::Source::View { view, .. } => {
+                let buffer = &buffers[view.buffer().index()];
+                let data = buffer.get(view.offset()..view.offset() + view.length())
+                    .ok_or_else(|| MeshError::Invalid(format!("image {} is out of bounds", image.index())))?;
+                decode_texture(data, path)?
+            }
+            ::gltf::image::Source::Uri { uri, .. } => match decode_data_uri(uri) {
+                Some(data) => decode_texture(&data?, path)?,
+                None => decode_texture(&read_relative(path, uri)?, Path::new(uri))?,
+            },
+        };
+        textures.push(texture);
+    }
+
+    let scene = match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => scene,
+        None => return Ok(Mesh { submeshes: Vec::new(), textures }),
+    };
+    let mut submeshes = Vec::new();
+    let mut visited = HashSet::new();
+    for node in scene.nodes() {
+        load_node(&node, cgmath::Matrix4::identity(), &buffers, &mut visited, &mut submeshes)?;
+    }
+    Ok(Mesh { submeshes, textures })
+}
+
+fn load_node(
+    node: &::gltf::Node,
+    parent: cgmath::Matrix4<f32>,
+    buffers: &[Vec<u8>],
+    visited: &mut HashSet<usize>,
+    submeshes: &mut Vec<Submesh>,
+) -> Result<(), MeshError> {
+    // Nodes form trees, so one that comes up again is part of a cycle or has
+    // more than one parent
+    if !visited.insert(node.index()) {
+        return Err(MeshError::Invalid(format!("node {} is in the scene more than once", node.index())));
+    }
+    let transform = parent * cgmath::Matrix4::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                warn!("Skipping a primitive of mesh {} that isn't made of triangles", mesh.index());
+                continue;
+            }
+            submeshes.push(load_primitive(&primitive, transform, buffers)?);
+        }
+    }
+    for child in node.children() {
+        load_node(&child, transform, buffers, visited, submeshes)?;
+    }
+    Ok(())
+}
+
+fn load_primitive(
+    primitive: &::gltf::Primitive,
+    transform: cgmath::Matrix4<f32>,
+    buffers: &[Vec<u8>],
+) -> Result<Submesh, MeshError> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let positions: Vec<[f32; 3]> = reader.read_positions()
+        .ok_or_else(|| MeshError::Invalid("a primitive has no positions".to_string()))?
+        .collect();
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+    let tex_coords: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|tex_coords| tex_coords.into_f32().collect());
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    let normal_matrix = {
+        let m = transform;
+        let linear = cgmath::Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate());
+        // Normals stay perpendicular to surfaces under the inverse transpose
+        linear.invert().map(|inverse| inverse.transpose()).unwrap_or(linear)
+    };
+    let mut vertices: Vec<MeshVertex> = positions.iter().enumerate()
+        .map(|(i, &position)| {
+            let position = transform * cgmath::Vector3::from(position).extend(1.0);
+            let normal = normals.as_ref()
+                .and_then(|normals| normals.get(i))
+                .map(|&normal| (normal_matrix * cgmath::Vector3::from(normal)).normalize().into())
+                .unwrap_or([0.0, 0.0, 0.0]);
+            let tex_coord = tex_coords.as_ref()
+                .and_then(|tex_coords| tex_coords.get(i))
+                .cloned()
+                .unwrap_or([0.0, 0.0]);
+            MeshVertex {
+                position: position.truncate().into(),
+                normal,
+                tex_coord,
+            }
+        })
+        .collect();
+    if normals.is_none() {
+        compute_normals(&mut vertices, &indices);
+    }
+
+    let pbr = primitive.material().pbr_metallic_roughness();
+    let material = Material {
+        base_color: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(|info| info.texture().source().index()),
+    };
+    Ok(Submesh { vertices, indices, material })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A gltf file with one triangle, its buffer embedded as a data uri.
+    /// The node moves it up by 2.
+    fn triangle(indexed: bool) -> String {
+        let mut data = Vec::new();
+        for &value in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for &index in &[0u16, 1, 2, 0] {
+            data.extend_from_slice(&index.to_le_bytes());
+        }
+        let indices = if indexed { r#", "indices": 1"# } else { "" };
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [{{ "mesh": 0, "translation": [0, 0, 2] }}],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}{}, "material": 0 }}] }}],
+                "materials": [{{ "pbrMetallicRoughness": {{ "baseColorFactor": [0.5, 1, 1, 1] }} }}],
+                "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0] }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ]
+            }}"#,
+            indices,
+            data.len(),
+            base64::encode(&data),
+        )
+    }
+
+    #[test]
+    fn loads_embedded_triangles() {
+        let mesh = load(triangle(true).as_bytes(), Path::new("triangle.gltf")).unwrap();
+        assert_eq!(mesh.submeshes.len(), 1);
+        let submesh = &mesh.submeshes[0];
+        assert_eq!(submesh.indices, vec![0, 1, 2]);
+        assert_eq!(submesh.vertices[1].position, [1.0, 0.0, 2.0]);
+        assert_eq!(submesh.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(submesh.material.base_color, [0.5, 1.0, 1.0, 1.0]);
+        assert_eq!(submesh.material.base_color_texture, None);
+    }
+
+    #[test]
+    fn unindexed_primitives_get_indices() {
+        let mesh = load(triangle(false).as_bytes(), Path::new("triangle.gltf")).unwrap();
+        assert_eq!(mesh.submeshes[0].indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn missing_buffers_are_errors() {
+        let gltf = triangle(true).replace("data:application/octet-stream;base64,", "missing-");
+        match load(gltf.as_bytes(), Path::new("triangle.gltf")) {
+            Err(MeshError::Io { .. }) => {}
+            other => panic!("expected an io error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn node_cycles_are_errors() {
+        let gltf = triangle(true).replace(
+            r#""nodes": [{ "mesh": 0, "translation": [0, 0, 2] }]"#,
+            r#""nodes": [{ "mesh": 0, "children": [1] }, { "children": [0] }]"#,
+        );
+        match load(gltf.as_bytes(), Path::new("triangle.gltf")) {
+            Err(MeshError::Invalid(_)) => {}
+            other => panic!("expected an invalid mesh error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Meshes loaded from model files, for the `MeshRenderer`. Wavefront OBJ
+//! files with their MTL materials and glTF 2.0 files are supported.
+
+use std::path::{Path, PathBuf};
+
+use cgmath::InnerSpace;
+
+use crate::lib::assets::Asset;
+
+mod gltf;
+mod obj;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// With 0, 0 at the top left of the texture.
+    pub tex_coord: [f32; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    /// Multiplied with the base color texture, in linear space.
+    pub base_color: [f32; 4],
+    /// An index into the mesh's textures.
+    pub base_color_texture: Option<usize>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+        }
+    }
+}
+
+/// A part of a mesh with a single material, drawn as a triangle list.
+#[derive(Debug, Clone)]
+pub struct Submesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    pub material: Material,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub submeshes: Vec<Submesh>,
+    /// The textures the materials use, decoded to rgba.
+    pub textures: Vec<image::RgbaImage>,
+}
+
+impl Mesh {
+    /// Loads a Wavefront OBJ file. Materials and textures are looked up
+    /// relative to `path`.
+    pub fn from_obj(bytes: &[u8], path: &Path) -> Result<Self, MeshError> {
+        obj::load(bytes, path)
+    }
+
+    /// Loads a glTF 2.0 file, either as json or binary. External buffers
+    /// and images are looked up relative to `path`. Every mesh in the
+    /// default scene is loaded, with its node's transform baked in.
+    pub fn from_gltf(bytes: &[u8], path: &Path) -> Result<Self, MeshError> {
+        gltf::load(bytes, path)
+    }
+}
+
+impl Asset for Mesh {
+    fn from_bytes(bytes: &[u8], path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let is = |format: &str| path.extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| extension.eq_ignore_ascii_case(format));
+        let mesh = if is("obj") {
+            Mesh::from_obj(bytes, path)?
+        } else if is("gltf") || is("glb") {
+            Mesh::from_gltf(bytes, path)?
+        } else {
+            return Err(Box::new(MeshError::UnknownFormat(path.to_path_buf())));
+        };
+        Ok(mesh)
+    }
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    UnknownFormat(PathBuf),
+    Obj(tobj::LoadError),
+    Gltf(::gltf::Error),
+    Io { path: PathBuf, error: std::io::Error },
+    Texture { path: PathBuf, error: image::ImageError },
+    /// The file parsed, but something it refers to is missing or invalid.
+    Invalid(String),
+}
+
+impl std::fmt::Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MeshError::UnknownFormat(path) => write!(f, "{} isn't an obj or gltf file", path.display()),
+            MeshError::Obj(error) => write!(f, "invalid obj: {}", error),
+            MeshError::Gltf(error) => write!(f, "invalid gltf: {}", error),
+            MeshError::Io { path, error } => write!(f, "unable to read {}: {}", path.display(), error),
+            MeshError::Texture { path, error } => write!(f, "unable to decode {}: {}", path.display(), error),
+            MeshError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+/// Gives every vertex the average normal of the triangles using it, for
+/// models that don't come with normals.
+pub fn compute_normals(vertices: &mut [MeshVertex], indices: &[u32]) {
+    let mut normals = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let corners = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        if corners.iter().any(|&corner| corner >= vertices.len()) {
+            continue;
+        }
+        let position = |corner: usize| cgmath::Vector3::from(vertices[corner].position);
+        let (a, b, c) = (position(corners[0]), position(corners[1]), position(corners[2]));
+        // Not normalized, so bigger triangles count for more
+        let normal = (b - a).cross(c - a);
+        for &corner in &corners {
+            normals[corner] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0.0 {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+/// Decodes a `data:` uri, returning `None` for anything else.
+fn decode_data_uri(uri: &str) -> Option<Result<Vec<u8>, MeshError>> {
+    if !uri.starts_with("data:") {
+        return None;
+    }
+    let data = match uri.find(";base64,") {
+        Some(start) => &uri[start + ";base64,".len()..],
+        None => return Some(Err(MeshError::Invalid("only base64 data uris are supported".to_string()))),
+    };
+    Some(base64::decode(data).map_err(|e| MeshError::Invalid(format!("invalid data uri: {}", e))))
+}
+
+/// Reads a file that a model refers to, relative to the model's path.
+fn read_relative(path: &Path, uri: &str) -> Result<Vec<u8>, MeshError> {
+    let path = path.parent().unwrap_or_else(|| Path::new("")).join(uri);
+    std::fs::read(&path).map_err(|error| MeshError::Io { path, error })
+}
+
+fn decode_texture(bytes: &[u8], path: &Path) -> Result<image::RgbaImage, MeshError> {
+    image::load_from_memory(bytes)
+        .map(|image| image.to_rgba())
+        .map_err(|error| MeshError::Texture { path: path.to_path_buf(), error })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3]) -> MeshVertex {
+        MeshVertex {
+            position,
+            normal: [0.0, 0.0, 0.0],
+            tex_coord: [0.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn normals_follow_winding() {
+        let mut vertices = vec![vertex([0.0, 0.0, 0.0]), vertex([1.0, 0.0, 0.0]), vertex([0.0, 1.0, 0.0])];
+        compute_normals(&mut vertices, &[0, 1, 2]);
+        assert!(vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+        compute_normals(&mut vertices, &[0, 2, 1]);
+        assert!(vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn shared_vertices_average_their_normals() {
+        // Two faces of a cube meeting at an edge
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0]),
+            vertex([0.0, 0.0, 1.0]),
+        ];
+        compute_normals(&mut vertices, &[0, 1, 2, 0, 3, 1]);
+        let diagonal = 2.0_f32.sqrt() / 2.0;
+        let normal = cgmath::Vector3::from(vertices[0].normal);
+        assert!((normal - cgmath::Vector3::new(0.0, diagonal, diagonal)).magnitude() < 1e-6);
+        assert_eq!(vertices[2].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn data_uris() {
+        let decoded = decode_data_uri("data:application/octet-stream;base64,AQID").unwrap().unwrap();
+        assert_eq!(decoded, vec![1, 2, 3]);
+        assert!(decode_data_uri("model.bin").is_none());
+        assert!(decode_data_uri("data:text/plain,hello").unwrap().is_err());
+    }
+
+    #[test]
+    fn unknown_extensions_are_errors() {
+        let error = Mesh::from_bytes(b"", Path::new("model.fbx")).unwrap_err();
+        assert_eq!(error.to_string(), "model.fbx isn't an obj or gltf file");
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{compute_normals, decode_texture, read_relative, Material, Mesh, MeshError, MeshVertex, Submesh};
+
+pub(super) fn load(bytes: &[u8], path: &Path) -> Result<Mesh, MeshError> {
+    let mut reader = std::io::BufReader::new(bytes);
+    let (models, materials) = tobj::load_obj_buf(&mut reader, |mtl_path| {
+        let mtl_path = path.parent().unwrap_or_else(|| Path::new("")).join(mtl_path);
+        // A model without its materials is still worth drawing
+        tobj::load_mtl(&mtl_path).or_else(|e| {
+            warn!("Unable to load {}: {}", mtl_path.display(), e);
+            Ok((Vec::new(), HashMap::new()))
+        })
+    })
+    .map_err(MeshError::Obj)?;
+
+    let mut textures = Vec::new();
+    let mut texture_indices = HashMap::new();
+    let materials = materials
+        .iter()
+        .map(|material| {
+            let mut base_color_texture = None;
+            if !material.diffuse_texture.is_empty() {
+                let name = &material.diffuse_texture;
+                if let Some(&index) = texture_indices.get(name) {
+                    base_color_texture = Some(index);
+                } else {
+                    let bytes = read_relative(path, name)?;
+                    textures.push(decode_texture(&bytes, Path::new(name))?);
+                    texture_indices.insert(name.clone(), textures.len() - 1);
+                    base_color_texture = Some(textures.len() - 1);
+                }
+            }
+            let [r, g, b] = material.diffuse;
+            Ok(Material {
+                base_color: [r, g, b, material.dissolve],
+                base_color_texture,
+            })
+        })
+        .collect::<Result<Vec<_>, MeshError>>()?;
+
+    let submeshes = models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertex_count = mesh.positions.len() / 3;
+            let has_normals = mesh.normals.len() == vertex_count * 3;
+            let has_tex_coords = mesh.texcoords.len() == vertex_count * 2;
+            let mut vertices: Vec<MeshVertex> = (0..vertex_count)
+                .map(|i| MeshVertex {
+                    position: [mesh.positions[i * 3], mesh.positions[i * 3 + 1], mesh.positions[i * 3 + 2]],
+                    normal: if has_normals {
+                        [mesh.normals[i * 3], mesh.normals[i * 3 + 1], mesh.normals[i * 3 + 2]]
+                    } else {
+                        [0.0, 0.0, 0.0]
+                    },
+                    // OBJ puts 0, 0 at the bottom left of the texture
+                    tex_coord: if has_tex_coords {
+                        [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+                    } else {
+                        [0.0, 0.0]
+                    },
+                })
+                .collect();
+            if !has_normals {
+                compute_normals(&mut vertices, &mesh.indices);
+            }
+            let material = mesh.material_id
+                .and_then(|id| materials.get(id))
+                .cloned()
+                .unwrap_or_default();
+            Submesh {
+                vertices,
+                indices: mesh.indices,
+                material,
+            }
+        })
+        .collect();
+
+    Ok(Mesh { submeshes, textures })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::util::TempDir;
+
+    const QUAD: &str = "
+mtllib quad.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+usemtl red
+f 1/1 2/2 3/3 4/4
+";
+
+    const MTL: &str = "
+newmtl red
+Kd 1 0 0
+d 0.5
+map_Kd red.png
+";
+
+    #[test]
+    fn loads_triangulated_faces() {
+        let dir = TempDir::new("obj-faces");
+        let mesh = load(QUAD.as_bytes(), &dir.path().join("quad.obj")).unwrap();
+        assert_eq!(mesh.submeshes.len(), 1);
+        let submesh = &mesh.submeshes[0];
+        assert_eq!(submesh.vertices.len(), 4);
+        assert_eq!(submesh.indices.len(), 6);
+        // Normals were computed from the winding, and v flipped
+        assert_eq!(submesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(submesh.vertices[0].tex_coord, [0.0, 1.0]);
+        assert_eq!(submesh.vertices[2].tex_coord, [1.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_materials_use_the_default() {
+        let dir = TempDir::new("obj-no-mtl");
+        let mesh = load(QUAD.as_bytes(), &dir.path().join("quad.obj")).unwrap();
+        assert_eq!(mesh.submeshes[0].material, Material::default());
+        assert!(mesh.textures.is_empty());
+    }
+
+    #[test]
+    fn loads_materials_and_textures() {
+        let dir = TempDir::new("obj-mtl");
+        std::fs::write(dir.path().join("quad.mtl"), MTL).unwrap();
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]))
+            .save(dir.path().join("red.png"))
+            .unwrap();
+
+        let mesh = load(QUAD.as_bytes(), &dir.path().join("quad.obj")).unwrap();
+        let material = &mesh.submeshes[0].material;
+        assert_eq!(material.base_color, [1.0, 0.0, 0.0, 0.5]);
+        assert_eq!(material.base_color_texture, Some(0));
+        assert_eq!(mesh.textures[0].dimensions(), (2, 2));
+    }
+
+    #[test]
+    fn missing_textures_are_errors() {
+        let dir = TempDir::new("obj-no-texture");
+        std::fs::write(dir.path().join("quad.mtl"), MTL).unwrap();
+        match load(QUAD.as_bytes(), &dir.path().join("quad.obj")) {
+            Err(MeshError::Io { .. }) => {}
+            other => panic!("expected an io error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
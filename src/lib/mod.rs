@@ -1,3 +1,13 @@
+pub mod actions;
+pub mod app;
+pub mod assets;
 pub mod camera;
+pub mod components;
+pub mod controllers;
 pub mod graphics;
+pub mod input;
+pub mod mesh;
+pub mod shader;
+pub mod systems;
+pub mod time;
 pub mod util;
\ No newline at end of file
@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use super::{Preprocessor, ShaderError};
+use crate::lib::util::{self, ShaderStage};
+
+/// Bump this to throw away everything cached by older versions, for example
+/// after upgrading the compiler.
+const CACHE_VERSION: u32 = 1;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Identifies one permutation of a shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShaderKey(u64);
+
+impl ShaderKey {
+    /// Hashes the preprocessed source along with the stage and defines. The
+    /// hash is stable between runs, so it can name files on disk.
+    pub fn new(source: &str, stage: ShaderStage, defines: &[(String, String)]) -> Self {
+        let mut hash = Fnv1a::new();
+        hash.write(&CACHE_VERSION.to_le_bytes());
+        hash.write(&[stage as u8]);
+        for (name, value) in defines {
+            hash.write(name.as_bytes());
+            hash.write(&[0]);
+            hash.write(value.as_bytes());
+            hash.write(&[0]);
+        }
+        hash.write(source.as_bytes());
+        ShaderKey(hash.finish())
+    }
+
+    fn file_name(self) -> String {
+        format!("{:016x}.spv", self.0)
+    }
+}
+
+/// Keeps compiled SPIR-V around, so each permutation of a shader only gets
+/// compiled once. With a directory, the SPIR-V is also saved to disk, and
+/// later runs don't need to compile at all.
+#[derive(Debug, Default)]
+pub struct ShaderCache {
+    memory: HashMap<ShaderKey, Vec<u32>>,
+    directory: Option<PathBuf>,
+}
+
+impl ShaderCache {
+    /// A cache that only lasts as long as it does.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// A cache that also saves to `directory`, creating it when needed.
+    pub fn with_directory<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            memory: HashMap::new(),
+            directory: Some(directory.into()),
+        }
+    }
+
+    /// Preprocesses and compiles a shader, unless this permutation of it
+    /// has been compiled before.
+    pub fn compile(
+        &mut self,
+        preprocessor: &Preprocessor,
+        name: &str,
+        code: &str,
+        stage: ShaderStage,
+    ) -> Result<Vec<u32>, ShaderError> {
+        let preprocessed = preprocessor.process(name, code)
+            .map_err(|e| e.into_shader_error(name, stage))?;
+        let key = ShaderKey::new(&preprocessed.code, stage, preprocessor.defines());
+        self.get_or_compile(key, || {
+            util::load_glsl(name, &preprocessed.code, stage).map_err(|e| preprocessed.remap(e))
+        })
+    }
+
+    /// Looks for `key` in memory, then on disk, and only calls `compile` if
+    /// it's in neither.
+    pub fn get_or_compile<F>(&mut self, key: ShaderKey, compile: F) -> Result<Vec<u32>, ShaderError>
+    where
+        F: FnOnce() -> Result<Vec<u32>, ShaderError>,
+    {
+        if let Some(spirv) = self.memory.get(&key) {
+            return Ok(spirv.clone());
+        }
+
+        let spirv = match self.read(key) {
+            Some(spirv) => spirv,
+            None => {
+                let spirv = compile()?;
+                self.write(key, &spirv);
+                spirv
+            }
+        };
+        self.memory.insert(key, spirv.clone());
+        Ok(spirv)
+    }
+
+    pub fn contains(&self, key: ShaderKey) -> bool {
+        if self.memory.contains_key(&key) {
+            return true;
+        }
+        match self.path(key) {
+            Some(path) => path.is_file(),
+            None => false,
+        }
+    }
+
+    /// Forgets one permutation, so it gets compiled again next time.
+    pub fn invalidate(&mut self, key: ShaderKey) {
+        self.memory.remove(&key);
+        if let Some(path) = self.path(key) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Unable to remove {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Forgets everything, including what's on disk.
+    pub fn clear(&mut self) {
+        self.memory.clear();
+        let entries = match self.directory.as_ref().and_then(|directory| std::fs::read_dir(directory).ok()) {
+            Some(entries) => entries,
+            None => return,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.extension() == Some(OsStr::new("spv")) {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Unable to remove {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    fn path(&self, key: ShaderKey) -> Option<PathBuf> {
+        self.directory.as_ref().map(|directory| directory.join(key.file_name()))
+    }
+
+    /// Reads cached SPIR-V from disk. Files that don't look like SPIR-V are
+    /// ignored, and get overwritten once the shader is compiled again.
+    fn read(&self, key: ShaderKey) -> Option<Vec<u32>> {
+        let path = self.path(key)?;
+        let bytes = std::fs::read(&path).ok()?;
+        let spirv = decode(&bytes);
+        if spirv.is_none() {
+            warn!("Ignoring invalid cached shader {}", path.display());
+        }
+        spirv
+    }
+
+    fn write(&self, key: ShaderKey, spirv: &[u32]) {
+        let path = match self.path(key) {
+            Some(path) => path,
+            None => return,
+        };
+        let result = create_parent(&path).and_then(|_| std::fs::write(&path, encode(spirv)));
+        if let Err(e) = result {
+            warn!("Unable to cache shader at {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => std::fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+fn encode(spirv: &[u32]) -> Vec<u8> {
+    spirv.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+}
+
+fn decode(bytes: &[u8]) -> Option<Vec<u32>> {
+    let chunks = bytes.chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    let words: Vec<u32> = chunks
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    if words.first() != Some(&SPIRV_MAGIC) {
+        return None;
+    }
+    Some(words)
+}
+
+/// 64 bit FNV-1a, which unlike the std hashers is guaranteed to stay the
+/// same between Rust versions.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::util::TempDir;
+    use std::cell::Cell;
+
+    const SPIRV: &[u32] = &[SPIRV_MAGIC, 0x0001_0000, 1, 2, 3];
+
+    fn key(source: &str) -> ShaderKey {
+        ShaderKey::new(source, ShaderStage::Fragment, &[])
+    }
+
+    #[test]
+    fn fnv1a_matches_reference() {
+        let mut hash = Fnv1a::new();
+        hash.write(b"a");
+        assert_eq!(hash.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn keys_depend_on_source_stage_and_defines() {
+        let defines = vec![("TEXTURED".to_string(), "1".to_string())];
+        let base = ShaderKey::new("void main() {}", ShaderStage::Fragment, &[]);
+        assert_eq!(base, ShaderKey::new("void main() {}", ShaderStage::Fragment, &[]));
+        assert_ne!(base, ShaderKey::new("void main() { }", ShaderStage::Fragment, &[]));
+        assert_ne!(base, ShaderKey::new("void main() {}", ShaderStage::Vertex, &[]));
+        assert_ne!(base, ShaderKey::new("void main() {}", ShaderStage::Fragment, &defines));
+    }
+
+    #[test]
+    fn compiles_once_in_memory() {
+        let mut cache = ShaderCache::in_memory();
+        let compiles = Cell::new(0);
+        let compile = || {
+            compiles.set(compiles.get() + 1);
+            Ok(SPIRV.to_vec())
+        };
+        assert_eq!(cache.get_or_compile(key("a"), compile).unwrap(), SPIRV);
+        assert_eq!(cache.get_or_compile(key("a"), compile).unwrap(), SPIRV);
+        assert_eq!(compiles.get(), 1);
+    }
+
+    #[test]
+    fn later_runs_load_from_disk() {
+        let dir = TempDir::new("shader-cache-disk");
+        ShaderCache::with_directory(dir.path()).get_or_compile(key("a"), || Ok(SPIRV.to_vec())).unwrap();
+
+        let mut next_run = ShaderCache::with_directory(dir.path());
+        assert!(next_run.contains(key("a")));
+        let spirv = next_run.get_or_compile(key("a"), || panic!("should have been cached")).unwrap();
+        assert_eq!(spirv, SPIRV);
+    }
+
+    #[test]
+    fn invalidate_recompiles() {
+        let dir = TempDir::new("shader-cache-invalidate");
+        let mut cache = ShaderCache::with_directory(dir.path());
+        cache.get_or_compile(key("a"), || Ok(SPIRV.to_vec())).unwrap();
+        cache.get_or_compile(key("b"), || Ok(SPIRV.to_vec())).unwrap();
+
+        cache.invalidate(key("a"));
+        assert!(!cache.contains(key("a")));
+        assert!(cache.contains(key("b")));
+        assert!(!ShaderCache::with_directory(dir.path()).contains(key("a")));
+
+        let compiles = Cell::new(0);
+        cache.get_or_compile(key("a"), || {
+            compiles.set(compiles.get() + 1);
+            Ok(SPIRV.to_vec())
+        }).unwrap();
+        assert_eq!(compiles.get(), 1);
+    }
+
+    #[test]
+    fn clear_removes_everything() {
+        let dir = TempDir::new("shader-cache-clear");
+        let mut cache = ShaderCache::with_directory(dir.path());
+        cache.get_or_compile(key("a"), || Ok(SPIRV.to_vec())).unwrap();
+        cache.clear();
+        assert!(!cache.contains(key("a")));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn corrupt_files_are_recompiled() {
+        let dir = TempDir::new("shader-cache-corrupt");
+        std::fs::write(dir.path().join(key("a").file_name()), b"garbage!").unwrap();
+
+        let mut cache = ShaderCache::with_directory(dir.path());
+        assert_eq!(cache.get_or_compile(key("a"), || Ok(SPIRV.to_vec())).unwrap(), SPIRV);
+        assert_eq!(std::fs::read(dir.path().join(key("a").file_name())).unwrap(), encode(SPIRV));
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let mut cache = ShaderCache::in_memory();
+        let error = ShaderError::from_output("a.frag", ShaderStage::Fragment, "ERROR: 0:1: 'x' : nope");
+        assert!(cache.get_or_compile(key("a"), || Err(error)).is_err());
+        assert!(!cache.contains(key("a")));
+    }
+}
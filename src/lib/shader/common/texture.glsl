@@ -0,0 +1,6 @@
+layout(set = 0, binding = 1) uniform texture2D t_color;
+layout(set = 0, binding = 2) uniform sampler s_color;
+
+vec4 sample_color(vec2 tex_coord) {
+    return texture(sampler2D(t_color, s_color), tex_coord);
+}
@@ -0,0 +1,183 @@
+use crate::lib::util::ShaderStage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One message from the shader compiler. Messages about the shader as a
+/// whole, like the error count, have no line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderMessage {
+    pub severity: Severity,
+    /// The file the message is about, when it isn't the shader itself.
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+/// Why a shader couldn't be compiled.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub stage: ShaderStage,
+    /// Usually the path of the source file.
+    pub name: String,
+    pub messages: Vec<ShaderMessage>,
+}
+
+impl ShaderError {
+    /// Builds an error from the compiler's output. Output that doesn't look
+    /// like glslang messages is kept as a single message.
+    pub fn from_output(name: &str, stage: ShaderStage, output: &str) -> Self {
+        let mut messages = parse_messages(output);
+        if messages.is_empty() {
+            messages.push(ShaderMessage {
+                severity: Severity::Error,
+                file: None,
+                line: None,
+                column: None,
+                message: output.trim().to_string(),
+            });
+        }
+        Self {
+            stage,
+            name: name.to_string(),
+            messages,
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ShaderMessage> {
+        self.messages.iter().filter(|message| message.severity == Severity::Error)
+    }
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unable to compile {:?} shader {}", self.stage, self.name)?;
+        for message in &self.messages {
+            write!(f, "\n  {}", message.file.as_ref().unwrap_or(&self.name))?;
+            if let Some(line) = message.line {
+                write!(f, ":{}", line)?;
+            }
+            if let Some(column) = message.column {
+                write!(f, ":{}", column)?;
+            }
+            let severity = match message.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            write!(f, ": {}: {}", severity, message.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Parses glslang output like `ERROR: 0:12: 'foo' : undeclared identifier`.
+/// The part before the line is the source string, which glslang reports
+/// either as an index or as a file name.
+fn parse_messages(output: &str) -> Vec<ShaderMessage> {
+    output.lines().filter_map(parse_message).collect()
+}
+
+fn parse_message(line: &str) -> Option<ShaderMessage> {
+    let line = line.trim();
+    let mut parts = line.splitn(2, ": ");
+    let severity = match parts.next() {
+        Some("ERROR") => Severity::Error,
+        Some("WARNING") => Severity::Warning,
+        _ => return None,
+    };
+    let rest = parts.next()?;
+
+    let located = rest.find(": ").and_then(|split| {
+        let (line, column) = parse_location(&rest[..split])?;
+        Some(ShaderMessage {
+            severity,
+            file: None,
+            line: Some(line),
+            column,
+            message: rest[split + 2..].trim().to_string(),
+        })
+    });
+    Some(located.unwrap_or_else(|| ShaderMessage {
+        severity,
+        file: None,
+        line: None,
+        column: None,
+        message: rest.trim().to_string(),
+    }))
+}
+
+/// Splits `source:line` or `source:line:column` into the line and column.
+fn parse_location(location: &str) -> Option<(u32, Option<u32>)> {
+    let parts: Vec<&str> = location.split(':').collect();
+    let number = |part: &str| part.trim().parse::<u32>().ok();
+    match parts.len() {
+        n if n >= 3 => match (number(parts[n - 2]), number(parts[n - 1])) {
+            (Some(line), Some(column)) => Some((line, Some(column))),
+            (_, Some(line)) => Some((line, None)),
+            _ => None,
+        },
+        2 => number(parts[1]).map(|line| (line, None)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "\
+/tmp/.tmpXyZ/0.frag
+ERROR: 0:12: 'foo' : undeclared identifier
+WARNING: 0:3:7: 'bar' : unused
+ERROR: /src/shader.frag:20: '' : compilation terminated
+ERROR: 2 compilation errors.  No code generated.
+";
+
+    #[test]
+    fn parses_lines_and_columns() {
+        let error = ShaderError::from_output("shader.frag", ShaderStage::Fragment, OUTPUT);
+        assert_eq!(error.messages.len(), 4);
+        assert_eq!(error.messages[0], ShaderMessage {
+            severity: Severity::Error,
+            file: None,
+            line: Some(12),
+            column: None,
+            message: "'foo' : undeclared identifier".to_string(),
+        });
+        assert_eq!(error.messages[1].severity, Severity::Warning);
+        assert_eq!((error.messages[1].line, error.messages[1].column), (Some(3), Some(7)));
+        assert_eq!(error.messages[2].line, Some(20));
+    }
+
+    #[test]
+    fn summary_has_no_line() {
+        let error = ShaderError::from_output("shader.frag", ShaderStage::Fragment, OUTPUT);
+        let summary = &error.messages[3];
+        assert_eq!(summary.line, None);
+        assert_eq!(summary.message, "2 compilation errors.  No code generated.");
+        assert_eq!(error.errors().count(), 3);
+    }
+
+    #[test]
+    fn unrecognized_output_is_kept() {
+        let error = ShaderError::from_output("shader.vert", ShaderStage::Vertex, "glslangValidator not found\n");
+        assert_eq!(error.messages.len(), 1);
+        assert_eq!(error.messages[0].message, "glslangValidator not found");
+        assert_eq!(error.messages[0].line, None);
+    }
+
+    #[test]
+    fn display_points_at_the_source() {
+        let error = ShaderError::from_output("shader.frag", ShaderStage::Fragment, "ERROR: 0:12: 'foo' : undeclared identifier");
+        assert_eq!(
+            error.to_string(),
+            "unable to compile Fragment shader shader.frag\n  shader.frag:12: error: 'foo' : undeclared identifier",
+        );
+    }
+}
@@ -0,0 +1,59 @@
+mod cache;
+mod error;
+mod preprocess;
+mod watcher;
+
+pub use cache::*;
+pub use error::*;
+pub use preprocess::*;
+pub use watcher::*;
+
+use crate::lib::util;
+
+/// Where includes are looked up when shaders are reloaded from disk.
+const INCLUDE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/shader");
+
+/// A preprocessor that can include the snippets in `common/`, which are
+/// compiled into the binary.
+pub fn embedded_includes() -> Preprocessor {
+    Preprocessor::new().with_file("common/texture.glsl", include_str!("common/texture.glsl"))
+}
+
+/// A shader that's compiled into the binary, along with the path of its
+/// source file so it can be reloaded during development.
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderSource {
+    pub path: &'static str,
+    pub code: &'static str,
+    pub stage: util::ShaderStage,
+}
+
+impl ShaderSource {
+    pub const fn new(path: &'static str, code: &'static str, stage: util::ShaderStage) -> Self {
+        Self { path, code, stage }
+    }
+
+    /// Compiles the code that was embedded at build time, or takes it from
+    /// the cache if it was compiled before.
+    pub fn compile(&self, cache: &mut ShaderCache) -> Result<Vec<u32>, ShaderError> {
+        self.compile_with(cache, &embedded_includes())
+    }
+
+    /// Compiles the embedded code with a preprocessor of your own, for
+    /// example to add defines. Each set of defines is cached separately.
+    pub fn compile_with(&self, cache: &mut ShaderCache, preprocessor: &Preprocessor) -> Result<Vec<u32>, ShaderError> {
+        cache.compile(preprocessor, self.path, self.code, self.stage)
+    }
+
+    /// Compiles whatever is in the source file right now, with includes
+    /// also read from disk. This skips the cache, as the files are expected
+    /// to change.
+    pub fn compile_from_disk(&self) -> Result<Vec<u32>, ShaderError> {
+        let code = std::fs::read_to_string(self.path).map_err(|e| {
+            ShaderError::from_output(self.path, self.stage, &format!("unable to read the source: {}", e))
+        })?;
+        Preprocessor::new()
+            .with_directory(INCLUDE_DIR)
+            .compile(self.path, &code, self.stage)
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use super::{Severity, ShaderError, ShaderMessage};
+use crate::lib::util::{self, ShaderStage};
+
+/// The name that injected `#define`s are reported under.
+const DEFINES_NAME: &str = "<defines>";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreprocessError {
+    IncludeNotFound { file: String, line: u32, include: String },
+    IncludeCycle { file: String, line: u32, include: String },
+    MalformedInclude { file: String, line: u32 },
+}
+
+impl std::fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PreprocessError::IncludeNotFound { file, line, include } => {
+                write!(f, "{}:{}: unable to find include \"{}\"", file, line, include)
+            }
+            PreprocessError::IncludeCycle { file, line, include } => {
+                write!(f, "{}:{}: \"{}\" includes itself", file, line, include)
+            }
+            PreprocessError::MalformedInclude { file, line } => {
+                write!(f, "{}:{}: expected #include \"file\"", file, line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+impl PreprocessError {
+    /// Reports the error like a compiler error in the shader `name`.
+    pub fn into_shader_error(self, name: &str, stage: ShaderStage) -> ShaderError {
+        let (file, line) = match &self {
+            PreprocessError::IncludeNotFound { file, line, .. }
+            | PreprocessError::IncludeCycle { file, line, .. }
+            | PreprocessError::MalformedInclude { file, line } => (file.clone(), *line),
+        };
+        ShaderError {
+            stage,
+            name: name.to_string(),
+            messages: vec![ShaderMessage {
+                severity: Severity::Error,
+                file: Some(file),
+                line: Some(line),
+                column: None,
+                message: self.to_string(),
+            }],
+        }
+    }
+}
+
+/// Resolves `#include "file"` and adds `#define`s before a shader is
+/// compiled. Includes are looked up in the files added with `with_file`
+/// first, then in the directory if there is one. Every file is included at
+/// most once.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    files: HashMap<String, String>,
+    directory: Option<PathBuf>,
+    defines: Vec<(String, String)>,
+}
+
+/// Preprocessed code, along with where each of its lines came from.
+#[derive(Debug, Clone)]
+pub struct Preprocessed {
+    pub code: String,
+    lines: Vec<(String, u32)>,
+    files: Vec<PathBuf>,
+}
+
+impl Preprocessed {
+    /// The includes that were read from the directory, in the order they
+    /// were included. Files added with `with_file` aren't listed.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// The file and line that a line of the preprocessed code came from.
+    /// Lines start at 1, like compiler messages.
+    pub fn source_line(&self, line: u32) -> Option<(&str, u32)> {
+        let index = (line as usize).checked_sub(1)?;
+        self.lines.get(index).map(|(file, line)| (file.as_str(), *line))
+    }
+
+    /// Points the messages in `error` at the original files and lines.
+    pub fn remap(&self, mut error: ShaderError) -> ShaderError {
+        for message in &mut error.messages {
+            if let Some((file, line)) = message.line.and_then(|line| self.source_line(line)) {
+                message.file = Some(file.to_string());
+                message.line = Some(line);
+            }
+        }
+        error
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `code` available to `#include "name"`.
+    pub fn with_file(mut self, name: &str, code: &str) -> Self {
+        self.files.insert(name.to_string(), code.to_string());
+        self
+    }
+
+    /// Looks up includes that weren't added with `with_file` relative to
+    /// `directory`.
+    pub fn with_directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Adds `#define name value` right after the `#version` line.
+    pub fn with_define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn defines(&self) -> &[(String, String)] {
+        &self.defines
+    }
+
+    pub fn process(&self, name: &str, code: &str) -> Result<Preprocessed, PreprocessError> {
+        let mut output = Output {
+            code: String::new(),
+            lines: Vec::new(),
+            files: Vec::new(),
+            included: HashSet::new(),
+            stack: vec![name.to_string()],
+        };
+        output.included.insert(name.to_string());
+
+        let has_version = code.lines().any(|line| line.trim_start().starts_with("#version"));
+        if !has_version {
+            self.push_defines(&mut output);
+        }
+        self.process_file(name, code, &mut output)?;
+
+        Ok(Preprocessed {
+            code: output.code,
+            lines: output.lines,
+            files: output.files,
+        })
+    }
+
+    /// Preprocesses and compiles a shader, reporting errors against the
+    /// original files.
+    pub fn compile(&self, name: &str, code: &str, stage: ShaderStage) -> Result<Vec<u32>, ShaderError> {
+        let preprocessed = self.process(name, code).map_err(|e| e.into_shader_error(name, stage))?;
+        util::load_glsl(name, &preprocessed.code, stage).map_err(|e| preprocessed.remap(e))
+    }
+
+    fn process_file(&self, name: &str, code: &str, output: &mut Output) -> Result<(), PreprocessError> {
+        for (index, line) in code.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let directive = line.trim_start();
+
+            if directive.starts_with("#include") {
+                let include = parse_include(directive).ok_or_else(|| PreprocessError::MalformedInclude {
+                    file: name.to_string(),
+                    line: line_number,
+                })?;
+                if output.stack.iter().any(|file| file == include) {
+                    return Err(PreprocessError::IncludeCycle {
+                        file: name.to_string(),
+                        line: line_number,
+                        include: include.to_string(),
+                    });
+                }
+                if !output.included.insert(include.to_string()) {
+                    continue;
+                }
+
+                let (included, path) = self.resolve(include).ok_or_else(|| PreprocessError::IncludeNotFound {
+                    file: name.to_string(),
+                    line: line_number,
+                    include: include.to_string(),
+                })?;
+                output.files.extend(path);
+                output.stack.push(include.to_string());
+                self.process_file(include, &included, output)?;
+                output.stack.pop();
+                continue;
+            }
+
+            output.push(line, name, line_number);
+            if directive.starts_with("#version") {
+                self.push_defines(output);
+            }
+        }
+        Ok(())
+    }
+
+    fn push_defines(&self, output: &mut Output) {
+        for (index, (name, value)) in self.defines.iter().enumerate() {
+            output.push(&format!("#define {} {}", name, value), DEFINES_NAME, index as u32 + 1);
+        }
+    }
+
+    /// The code of an include, and its path if it was read from disk.
+    fn resolve(&self, include: &str) -> Option<(String, Option<PathBuf>)> {
+        if let Some(code) = self.files.get(include) {
+            return Some((code.clone(), None));
+        }
+        let path = self.directory.as_ref().map(|directory| directory.join(include))?;
+        let code = std::fs::read_to_string(&path).ok()?;
+        Some((code, Some(path)))
+    }
+}
+
+struct Output {
+    code: String,
+    lines: Vec<(String, u32)>,
+    files: Vec<PathBuf>,
+    included: HashSet<String>,
+    stack: Vec<String>,
+}
+
+impl Output {
+    fn push(&mut self, line: &str, file: &str, line_number: u32) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.lines.push((file.to_string(), line_number));
+    }
+}
+
+/// The file name from `#include "file"` or `#include <file>`.
+fn parse_include(directive: &str) -> Option<&str> {
+    let rest = directive["#include".len()..].trim();
+    let (open, close) = match rest.chars().next()? {
+        '"' => ('"', '"'),
+        '<' => ('<', '>'),
+        _ => return None,
+    };
+    let rest = &rest[open.len_utf8()..];
+    let end = rest.find(close)?;
+    Some(&rest[..end]).filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::util::TempDir;
+
+    const MAIN: &str = "#version 450\n#include \"common/color.glsl\"\nvoid main() {}\n";
+    const COLOR: &str = "vec4 color() {\n    return vec4(1.0);\n}\n";
+
+    #[test]
+    fn includes_files() {
+        let preprocessed = Preprocessor::new()
+            .with_file("common/color.glsl", COLOR)
+            .process("main.frag", MAIN)
+            .unwrap();
+        assert_eq!(preprocessed.code, format!("#version 450\n{}void main() {{}}\n", COLOR));
+    }
+
+    #[test]
+    fn lines_map_back_to_their_files() {
+        let preprocessed = Preprocessor::new()
+            .with_file("common/color.glsl", COLOR)
+            .process("main.frag", MAIN)
+            .unwrap();
+        assert_eq!(preprocessed.source_line(1), Some(("main.frag", 1)));
+        assert_eq!(preprocessed.source_line(3), Some(("common/color.glsl", 2)));
+        assert_eq!(preprocessed.source_line(5), Some(("main.frag", 3)));
+        assert_eq!(preprocessed.source_line(6), None);
+    }
+
+    #[test]
+    fn defines_go_after_version() {
+        let preprocessed = Preprocessor::new()
+            .with_file("common/color.glsl", COLOR)
+            .with_define("USE_FOG", "1")
+            .process("main.frag", MAIN)
+            .unwrap();
+        assert!(preprocessed.code.starts_with("#version 450\n#define USE_FOG 1\n"));
+        assert_eq!(preprocessed.source_line(2), Some((DEFINES_NAME, 1)));
+        assert_eq!(preprocessed.source_line(3), Some(("common/color.glsl", 1)));
+    }
+
+    #[test]
+    fn defines_go_first_without_version() {
+        let preprocessed = Preprocessor::new()
+            .with_define("N", "4")
+            .process("snippet.glsl", "float x;\n")
+            .unwrap();
+        assert_eq!(preprocessed.code, "#define N 4\nfloat x;\n");
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let preprocessed = Preprocessor::new()
+            .with_file("a.glsl", "#include \"c.glsl\"\nfloat a;\n")
+            .with_file("b.glsl", "#include <c.glsl>\nfloat b;\n")
+            .with_file("c.glsl", "float c;\n")
+            .process("main.frag", "#include \"a.glsl\"\n#include \"b.glsl\"\n")
+            .unwrap();
+        assert_eq!(preprocessed.code, "float c;\nfloat a;\nfloat b;\n");
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let result = Preprocessor::new()
+            .with_file("a.glsl", "#include \"b.glsl\"\n")
+            .with_file("b.glsl", "\n#include \"a.glsl\"\n")
+            .process("main.frag", "#include \"a.glsl\"\n");
+        assert_eq!(result.unwrap_err(), PreprocessError::IncludeCycle {
+            file: "b.glsl".to_string(),
+            line: 2,
+            include: "a.glsl".to_string(),
+        });
+    }
+
+    #[test]
+    fn missing_and_malformed_includes() {
+        let preprocessor = Preprocessor::new();
+        assert_eq!(preprocessor.process("main.frag", "\n#include \"nope.glsl\"\n").unwrap_err(), PreprocessError::IncludeNotFound {
+            file: "main.frag".to_string(),
+            line: 2,
+            include: "nope.glsl".to_string(),
+        });
+        assert_eq!(preprocessor.process("main.frag", "#include nope.glsl\n").unwrap_err(), PreprocessError::MalformedInclude {
+            file: "main.frag".to_string(),
+            line: 1,
+        });
+    }
+
+    #[test]
+    fn includes_from_a_directory() {
+        let dir = TempDir::new("preprocess");
+        std::fs::create_dir_all(dir.path().join("common")).unwrap();
+        std::fs::write(dir.path().join("common/color.glsl"), COLOR).unwrap();
+
+        let preprocessed = Preprocessor::new()
+            .with_directory(dir.path())
+            .process("main.frag", MAIN)
+            .unwrap();
+        assert!(preprocessed.code.contains("return vec4(1.0);"));
+        assert_eq!(preprocessed.files(), &[dir.path().join("common/color.glsl")]);
+    }
+
+    #[test]
+    fn embedded_files_arent_listed() {
+        let preprocessed = Preprocessor::new()
+            .with_file("common/color.glsl", COLOR)
+            .process("main.frag", MAIN)
+            .unwrap();
+        assert!(preprocessed.files().is_empty());
+    }
+
+    #[test]
+    fn remap_points_messages_at_includes() {
+        let preprocessed = Preprocessor::new()
+            .with_file("common/color.glsl", COLOR)
+            .process("main.frag", MAIN)
+            .unwrap();
+        let error = ShaderError::from_output("main.frag", ShaderStage::Fragment, "ERROR: 0:3: 'retrun' : undeclared identifier");
+        let error = preprocessed.remap(error);
+        assert_eq!(error.messages[0].file, Some("common/color.glsl".to_string()));
+        assert_eq!(error.messages[0].line, Some(2));
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::{Preprocessor, ShaderSource, INCLUDE_DIR};
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches the source files of a set of shaders, along with the files they
+/// include, and recompiles the shaders when any of them change.
+#[derive(Debug)]
+pub struct ShaderWatcher {
+    sources: Vec<ShaderSource>,
+    include_dir: PathBuf,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(sources: &[ShaderSource]) -> Self {
+        Self::with_include_dir(sources, INCLUDE_DIR)
+    }
+
+    fn with_include_dir<P: Into<PathBuf>>(sources: &[ShaderSource], include_dir: P) -> Self {
+        let mut watcher = Self {
+            sources: sources.to_vec(),
+            include_dir: include_dir.into(),
+            files: Vec::new(),
+            last_check: Instant::now(),
+        };
+        watcher.watch_files();
+        watcher
+    }
+
+    /// Whether any of the files changed since the last time this was called.
+    /// The files are only checked every so often, so this is cheap to call
+    /// every frame.
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        self.poll()
+    }
+
+    /// Recompiles every shader from disk if any of them changed, returning
+    /// the SPIR-V in the order the sources were given. Nothing is returned
+    /// if any of them fail to compile, and the errors are logged instead.
+    pub fn reload(&mut self) -> Option<Vec<Vec<u32>>> {
+        if !self.changed() {
+            return None;
+        }
+
+        let mut compiled = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            match source.compile_from_disk() {
+                Ok(spirv) => compiled.push(spirv),
+                Err(e) => {
+                    error!("{}", e);
+                    return None;
+                }
+            }
+        }
+        info!("Reloaded {}", self.sources.iter().map(|source| source.path).collect::<Vec<_>>().join(", "));
+        Some(compiled)
+    }
+
+    fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        // An edit may have added includes
+        if changed {
+            self.watch_files();
+        }
+        changed
+    }
+
+    /// Starts watching the shader files and whatever they include, if they
+    /// aren't watched already. Files stay watched once they stop being
+    /// included, which is harmless and keeps broken includes watched.
+    fn watch_files(&mut self) {
+        for source in &self.sources {
+            let path = PathBuf::from(source.path);
+            let includes = includes(&path, &self.include_dir);
+            for file in std::iter::once(path).chain(includes) {
+                if !self.files.iter().any(|(watched, _)| *watched == file) {
+                    let modified = modified(&file);
+                    self.files.push((file, modified));
+                }
+            }
+        }
+    }
+}
+
+/// The files on disk that the shader at `path` includes. Shaders that
+/// can't be read or preprocessed don't include anything.
+fn includes(path: &Path, include_dir: &Path) -> Vec<PathBuf> {
+    let code = match std::fs::read_to_string(path) {
+        Ok(code) => code,
+        Err(_) => return Vec::new(),
+    };
+    Preprocessor::new()
+        .with_directory(include_dir)
+        .process(&path.to_string_lossy(), &code)
+        .map(|preprocessed| preprocessed.files().to_vec())
+        .unwrap_or_default()
+}
+
+fn modified<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::util::{ShaderStage, TempDir};
+
+    const PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/target/watcher-test.frag");
+    const INCLUDING_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/target/watcher-include-test.frag");
+
+    #[test]
+    fn notices_new_files() {
+        let _ = std::fs::remove_file(PATH);
+        let mut watcher = ShaderWatcher::new(&[ShaderSource::new(PATH, "", ShaderStage::Fragment)]);
+        assert!(!watcher.poll());
+
+        std::fs::write(PATH, "#version 450\nvoid main() {}\n").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+        std::fs::remove_file(PATH).unwrap();
+    }
+
+    #[test]
+    fn notices_changed_includes() {
+        let include_dir = TempDir::new("watcher");
+        let include = include_dir.path().join("common/color.glsl");
+        std::fs::create_dir_all(include.parent().unwrap()).unwrap();
+        std::fs::write(&include, "vec4 color() { return vec4(1.0); }\n").unwrap();
+        std::fs::write(INCLUDING_PATH, "#version 450\n#include \"common/color.glsl\"\nvoid main() {}\n").unwrap();
+
+        let source = ShaderSource::new(INCLUDING_PATH, "", ShaderStage::Fragment);
+        let mut watcher = ShaderWatcher::with_include_dir(&[source], include_dir.path());
+        assert!(watcher.files.iter().any(|(path, _)| *path == include));
+        assert!(!watcher.poll());
+
+        // Modification times can be coarse, so remove the file instead
+        std::fs::remove_file(&include).unwrap();
+        assert!(watcher.poll());
+        std::fs::remove_file(INCLUDING_PATH).unwrap();
+    }
+}
@@ -0,0 +1,610 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{SquareMatrix, VectorSpace};
+use specs::prelude::*;
+
+use crate::lib::{
+    assets::{Assets, Texture},
+    camera::{Camera2d, Frustum},
+    components::*,
+    graphics::{Atlas, Instance, MeshInstance},
+    mesh::Mesh,
+};
+
+/// Records where everything was before the systems run, so the frames
+/// between updates can be interpolated.
+pub struct SnapshotSystem;
+impl<'a> System<'a> for SnapshotSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Appearance>,
+        WriteStorage<'a, Previous>,
+    );
+
+    fn run(&mut self, (entities, r_pos, r_appearance, mut w_previous): Self::SystemData) {
+        for (entity, pos, appearance) in (&entities, &r_pos, &r_appearance).join() {
+            let previous = Previous {
+                position: pos.0,
+                rotation: appearance.rotation,
+            };
+            w_previous.insert(entity, previous).unwrap();
+        }
+    }
+}
+
+/// Builds the instances for the `QuadRenderer`, interpolating between the
+/// previous and current positions. Rotation is interpolated linearly, so it
+/// should change by less than half a turn per update. With a `Camera2d`,
+/// instances it can't see are left out, and counted in `CullStats`.
+pub struct InstanceUpdateSystem;
+impl<'a> System<'a> for InstanceUpdateSystem {
+    type SystemData = (
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Previous>,
+        ReadStorage<'a, Appearance>,
+        Read<'a, InterpolationAlpha>,
+        Read<'a, Atlas>,
+        Option<Read<'a, Camera2d>>,
+        Write<'a, CullStats>,
+        WriteExpect<'a, Vec<Instance>>,
+    );
+
+    fn run(
+        &mut self,
+        (r_pos, r_previous, r_appearance, alpha, atlas, camera, mut cull_stats, mut instances): Self::SystemData,
+    ) {
+        let alpha = alpha.0;
+        let frustum = camera.map(|camera| Frustum::from_matrix(camera.view_projection()));
+        let mut culled = 0;
+        instances.clear();
+        instances.extend((&r_pos, r_previous.maybe(), &r_appearance).join().map(|(pos, previous, appearance)| {
+            let (offset, rotation) = match previous {
+                Some(previous) => (
+                    previous.position.lerp(pos.0, alpha),
+                    previous.rotation + (appearance.rotation - previous.rotation) * alpha,
+                ),
+                None => (pos.0, appearance.rotation),
+            };
+            Instance {
+                offset,
+                origin: appearance.origin,
+                scale: appearance.scale,
+                rotation,
+                color: appearance.color,
+                uv: appearance.sprite
+                    .or_else(|| appearance.texture.as_ref().and_then(|texture| atlas.texture(texture.id())))
+                    .map_or_else(|| atlas.white(), |sprite| atlas.rect(sprite)),
+            }
+        }).filter(|instance| match &frustum {
+            Some(frustum) if !is_visible(frustum, instance) => {
+                culled += 1;
+                false
+            }
+            _ => true,
+        }));
+        *cull_stats = CullStats {
+            visible: instances.len(),
+            culled,
+        };
+    }
+}
+
+fn is_visible(frustum: &Frustum, instance: &Instance) -> bool {
+    let bounds = instance.bounds();
+    frustum.intersects_aabb(
+        cgmath::Point3::new(bounds.min.x, bounds.min.y, 0.0),
+        cgmath::Point3::new(bounds.max.x, bounds.max.y, 0.0),
+    )
+}
+
+/// Packs every loaded texture into the `Atlas` resource whenever textures
+/// are loaded or unloaded. Textures that no handles point at anymore get
+/// unloaded first.
+#[derive(Default)]
+pub struct TextureAtlasSystem {
+    version: Option<u64>,
+}
+
+impl<'a> System<'a> for TextureAtlasSystem {
+    type SystemData = (
+        Write<'a, Assets<Texture>>,
+        Write<'a, Atlas>,
+    );
+
+    fn run(&mut self, (mut textures, mut atlas): Self::SystemData) {
+        textures.collect_unused();
+        if self.version == Some(textures.version()) {
+            return;
+        }
+        self.version = Some(textures.version());
+
+        match Atlas::from_textures(&textures) {
+            Ok(packed) => *atlas = packed,
+            Err(e) => error!("Unable to update the texture atlas: {}", e),
+        }
+    }
+}
+
+/// Works out the `GlobalTransform` of every entity with a `Transform`,
+/// parents first. An entity whose `Parent` is gone or has no `Transform` is
+/// treated as a root, and so is the entity where a cycle of parents is
+/// found. Run it before the `MeshInstanceSystem`.
+#[derive(Default)]
+pub struct TransformSystem {
+    /// The entities that cycles were broken at last time, so each cycle is
+    /// only warned about once.
+    cycle_roots: HashSet<Entity>,
+}
+
+impl<'a> System<'a> for TransformSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Parent>,
+        WriteStorage<'a, GlobalTransform>,
+    );
+
+    fn run(&mut self, (entities, r_transform, r_parent, mut w_global): Self::SystemData) {
+        let mut globals = HashMap::new();
+        let mut cycle_roots = HashSet::new();
+        for (entity, _) in (&entities, &r_transform).join() {
+            propagate(entity, &r_transform, &r_parent, &mut globals, &mut cycle_roots);
+        }
+        for root in cycle_roots.difference(&self.cycle_roots) {
+            warn!("Entity {} is its own ancestor, treating it as a root", root.id());
+        }
+        self.cycle_roots = cycle_roots;
+        for (entity, matrix) in globals {
+            w_global.insert(entity, GlobalTransform(matrix)).unwrap();
+        }
+
+        let stale: Vec<Entity> = (&entities, &w_global, !&r_transform).join()
+            .map(|(entity, _, _)| entity)
+            .collect();
+        for entity in stale {
+            w_global.remove(entity);
+        }
+    }
+}
+
+/// Works out the world matrix of `entity` and any of its ancestors that
+/// aren't in `globals` yet, from the nearest one that is down. If the
+/// ancestors loop back around, the last one before that becomes a root and
+/// is added to `cycle_roots`.
+fn propagate(
+    entity: Entity,
+    transforms: &ReadStorage<Transform>,
+    parents: &ReadStorage<Parent>,
+    globals: &mut HashMap<Entity, cgmath::Matrix4<f32>>,
+    cycle_roots: &mut HashSet<Entity>,
+) {
+    if globals.contains_key(&entity) {
+        return;
+    }
+    let mut chain = vec![entity];
+    let mut matrix = cgmath::Matrix4::identity();
+    loop {
+        let parent = parents.get(chain[chain.len() - 1])
+            .map(|parent| parent.0)
+            .filter(|&parent| transforms.get(parent).is_some());
+        match parent {
+            Some(parent) if chain.contains(&parent) => {
+                cycle_roots.insert(chain[chain.len() - 1]);
+                break;
+            }
+            Some(parent) => match globals.get(&parent) {
+                Some(global) => {
+                    matrix = *global;
+                    break;
+                }
+                None => chain.push(parent),
+            },
+            None => break,
+        }
+    }
+    for entity in chain.into_iter().rev() {
+        matrix = matrix * transforms.get(entity).unwrap().matrix();
+        globals.insert(entity, matrix);
+    }
+}
+
+/// Builds the instances for the `MeshRenderer` from every entity with a
+/// `Transform` and a `MeshHandle`, placed by their `GlobalTransform` when
+/// they have one. Meshes that no handles point at anymore get unloaded.
+pub struct MeshInstanceSystem;
+impl<'a> System<'a> for MeshInstanceSystem {
+    type SystemData = (
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, GlobalTransform>,
+        ReadStorage<'a, MeshHandle>,
+        Write<'a, Assets<Mesh>>,
+        Write<'a, Vec<MeshInstance>>,
+    );
+
+    fn run(&mut self, (r_transform, r_global, r_mesh, mut meshes, mut instances): Self::SystemData) {
+        // Last frame's instances hold handles too
+        instances.clear();
+        meshes.collect_unused();
+        instances.extend((&r_transform, r_global.maybe(), &r_mesh).join().map(|(transform, global, mesh)| MeshInstance {
+            mesh: mesh.0.clone(),
+            model: global.map_or_else(|| transform.matrix(), |global| global.0),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Rotation3, Transform as _};
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Previous>();
+        world.register::<Appearance>();
+        world.register::<Transform>();
+        world.register::<MeshHandle>();
+        world.register::<Parent>();
+        world.register::<GlobalTransform>();
+        world.insert(InterpolationAlpha(0.25));
+        world.insert(Vec::<Instance>::new());
+        world.insert(Atlas::default());
+        world.insert(CullStats::default());
+        world.insert(Assets::<Mesh>::default());
+        world.insert(Vec::<MeshInstance>::new());
+        world
+    }
+
+    fn appearance(rotation: f32) -> Appearance {
+        Appearance {
+            color: (1.0, 1.0, 1.0).into(),
+            origin: (0.0, 0.0).into(),
+            scale: (1.0, 1.0).into(),
+            rotation,
+            sprite: None,
+            texture: None,
+        }
+    }
+
+    #[test]
+    fn snapshot_records_current_state() {
+        let mut world = world();
+        let entity = world.create_entity()
+            .with(Position((1.0, 2.0).into()))
+            .with(appearance(0.5))
+            .build();
+
+        SnapshotSystem.run_now(&world);
+
+        let previous = *world.read_storage::<Previous>().get(entity).unwrap();
+        assert_eq!(previous.position, (1.0, 2.0).into());
+        assert_eq!(previous.rotation, 0.5);
+    }
+
+    #[test]
+    fn instances_are_interpolated() {
+        let mut world = world();
+        world.create_entity()
+            .with(Position((4.0, 0.0).into()))
+            .with(Previous { position: (0.0, 0.0).into(), rotation: 0.0 })
+            .with(appearance(1.0))
+            .build();
+
+        InstanceUpdateSystem.run_now(&world);
+
+        let instances = world.read_resource::<Vec<Instance>>();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].offset, (1.0, 0.0).into());
+        assert_eq!(instances[0].rotation, 0.25);
+    }
+
+    #[test]
+    fn new_entities_use_current_state() {
+        let mut world = world();
+        world.create_entity()
+            .with(Position((4.0, 0.0).into()))
+            .with(appearance(1.0))
+            .build();
+
+        InstanceUpdateSystem.run_now(&world);
+
+        let instances = world.read_resource::<Vec<Instance>>();
+        assert_eq!(instances[0].offset, (4.0, 0.0).into());
+        assert_eq!(instances[0].rotation, 1.0);
+    }
+
+    #[test]
+    fn offscreen_instances_are_culled() {
+        let mut world = world();
+        world.insert(Camera2d::default());
+        for &(x, rotation) in &[(0.0, 0.0), (1.4, 0.0), (1.55, 0.0), (1.55, std::f32::consts::FRAC_PI_4), (5.0, 0.0)] {
+            world.create_entity()
+                .with(Position((x, 0.0).into()))
+                .with(appearance(rotation))
+                .build();
+        }
+
+        InstanceUpdateSystem.run_now(&world);
+
+        // The unrotated quad at 1.55 ends just past the edge of the view,
+        // but turning it reaches back in
+        let offsets: Vec<_> = world.read_resource::<Vec<Instance>>().iter().map(|i| (i.offset.x, i.rotation)).collect();
+        assert_eq!(offsets, vec![(0.0, 0.0), (1.4, 0.0), (1.55, std::f32::consts::FRAC_PI_4)]);
+        assert_eq!(*world.read_resource::<CullStats>(), CullStats { visible: 3, culled: 2 });
+    }
+
+    #[test]
+    fn culling_follows_the_camera() {
+        let mut world = world();
+        world.insert(Camera2d { position: (5.0, 0.0).into(), ..Camera2d::default() });
+        world.create_entity().with(Position((0.0, 0.0).into())).with(appearance(0.0)).build();
+        world.create_entity().with(Position((5.0, 0.0).into())).with(appearance(0.0)).build();
+
+        InstanceUpdateSystem.run_now(&world);
+
+        let instances = world.read_resource::<Vec<Instance>>();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].offset, (5.0, 0.0).into());
+    }
+
+    #[test]
+    fn nothing_is_culled_without_a_camera() {
+        let mut world = world();
+        world.create_entity().with(Position((100.0, 0.0).into())).with(appearance(0.0)).build();
+
+        InstanceUpdateSystem.run_now(&world);
+
+        assert_eq!(world.read_resource::<Vec<Instance>>().len(), 1);
+        assert_eq!(*world.read_resource::<CullStats>(), CullStats { visible: 1, culled: 0 });
+    }
+
+    #[test]
+    fn sprites_use_their_atlas_rect() {
+        let mut world = world();
+        let mut builder = crate::lib::graphics::AtlasBuilder::new();
+        builder.add("first", image::RgbaImage::new(2, 2));
+        let second = builder.add("second", image::RgbaImage::new(2, 2));
+        let atlas = builder.build().unwrap();
+        let rect = atlas.rect(second);
+        world.insert(atlas);
+        world.create_entity()
+            .with(Position((0.0, 0.0).into()))
+            .with(Appearance { sprite: Some(second), ..appearance(0.0) })
+            .build();
+
+        InstanceUpdateSystem.run_now(&world);
+
+        assert_eq!(world.read_resource::<Vec<Instance>>()[0].uv, rect);
+    }
+
+    #[test]
+    fn untextured_quads_use_the_white_texel() {
+        let mut world = world();
+        let mut textures = Assets::<Texture>::default();
+        let packed = textures.add(Texture::new(image::RgbaImage::new(2, 2)));
+        world.insert(Atlas::from_textures(&textures).unwrap());
+        // Loaded after the atlas was packed
+        let unpacked = textures.add(Texture::new(image::RgbaImage::new(2, 2)));
+        drop(packed);
+        world.create_entity().with(Position((0.0, 0.0).into())).with(appearance(0.0)).build();
+        world.create_entity()
+            .with(Position((0.0, 0.0).into()))
+            .with(Appearance { texture: Some(unpacked), ..appearance(0.0) })
+            .build();
+
+        InstanceUpdateSystem.run_now(&world);
+
+        let white = world.read_resource::<Atlas>().white();
+        let instances = world.read_resource::<Vec<Instance>>();
+        assert_eq!(instances.len(), 2);
+        assert!(instances.iter().all(|instance| instance.uv == white));
+    }
+
+    #[test]
+    fn textures_use_their_atlas_rect() {
+        let mut world = world();
+        world.insert(Assets::<Texture>::default());
+        let texture = world.write_resource::<Assets<Texture>>()
+            .add(Texture::new(image::RgbaImage::new(2, 2)));
+        world.create_entity()
+            .with(Position((0.0, 0.0).into()))
+            .with(Appearance { texture: Some(texture.clone()), ..appearance(0.0) })
+            .build();
+
+        TextureAtlasSystem::default().run_now(&world);
+        InstanceUpdateSystem.run_now(&world);
+
+        let atlas = world.read_resource::<Atlas>();
+        let rect = atlas.rect(atlas.texture(texture.id()).unwrap());
+        assert_eq!(world.read_resource::<Vec<Instance>>()[0].uv, rect);
+    }
+
+    #[test]
+    fn atlas_drops_unused_textures() {
+        let mut world = world();
+        let mut textures = Assets::<Texture>::default();
+        let texture = textures.add(Texture::new(image::RgbaImage::new(2, 2)));
+        world.insert(textures);
+        let mut system = TextureAtlasSystem::default();
+        system.run_now(&world);
+        assert!(world.read_resource::<Atlas>().texture(texture.id()).is_some());
+
+        let id = texture.id();
+        drop(texture);
+        system.run_now(&world);
+        assert!(world.read_resource::<Assets<Texture>>().is_empty());
+        assert!(world.read_resource::<Atlas>().texture(id).is_none());
+    }
+
+    #[test]
+    fn mesh_instances_follow_transforms() {
+        let mut world = world();
+        let mesh = world.write_resource::<Assets<Mesh>>().add(Mesh::default());
+        let transform = Transform::from_translation((1.0, 2.0, 3.0).into()).with_scale((2.0, 2.0, 2.0).into());
+        world.create_entity().with(transform).with(MeshHandle(mesh.clone())).build();
+        world.create_entity().with(Transform::default()).build();
+
+        MeshInstanceSystem.run_now(&world);
+
+        let instances = world.read_resource::<Vec<MeshInstance>>();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].mesh, mesh);
+        assert_eq!(instances[0].model, transform.matrix());
+    }
+
+    #[test]
+    fn unused_meshes_are_unloaded() {
+        let mut world = world();
+        let mesh = world.write_resource::<Assets<Mesh>>().add(Mesh::default());
+        let entity = world.create_entity().with(Transform::default()).with(MeshHandle(mesh)).build();
+        MeshInstanceSystem.run_now(&world);
+        assert_eq!(world.read_resource::<Assets<Mesh>>().len(), 1);
+
+        world.delete_entity(entity).unwrap();
+        world.maintain();
+        MeshInstanceSystem.run_now(&world);
+        assert!(world.read_resource::<Assets<Mesh>>().is_empty());
+        assert!(world.read_resource::<Vec<MeshInstance>>().is_empty());
+    }
+
+    fn global(world: &World, entity: Entity) -> cgmath::Matrix4<f32> {
+        world.read_storage::<GlobalTransform>().get(entity).unwrap().0
+    }
+
+    fn translation(x: f32, y: f32, z: f32) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation((x, y, z).into())
+    }
+
+    #[test]
+    fn deep_hierarchies_accumulate() {
+        let mut world = world();
+        let root = world.create_entity().with(Transform::from_translation((0.0, 0.0, 1.0).into())).build();
+        let mut leaf = root;
+        for _ in 0..100 {
+            leaf = world.create_entity()
+                .with(Transform::from_translation((1.0, 0.0, 0.0).into()))
+                .with(Parent(leaf))
+                .build();
+        }
+
+        TransformSystem::default().run_now(&world);
+
+        assert_eq!(global(&world, root), translation(0.0, 0.0, 1.0));
+        assert_eq!(global(&world, leaf), translation(100.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn parents_are_done_before_their_children() {
+        let mut world = world();
+        // The children come first in the storage
+        let child = world.create_entity().with(Transform::from_translation((1.0, 0.0, 0.0).into())).build();
+        let grandchild = world.create_entity().with(Transform::from_translation((0.0, 0.0, 1.0).into())).build();
+        let parent = world.create_entity()
+            .with(Transform::default()
+                .with_rotation(cgmath::Quaternion::from_angle_z(cgmath::Deg(90.0)))
+                .with_scale((2.0, 2.0, 2.0).into()))
+            .build();
+        world.write_storage::<Parent>().insert(child, Parent(parent)).unwrap();
+        world.write_storage::<Parent>().insert(grandchild, Parent(child)).unwrap();
+
+        TransformSystem::default().run_now(&world);
+
+        let origin = cgmath::Point3::new(0.0, 0.0, 0.0);
+        let position = global(&world, grandchild).transform_point(origin);
+        assert!((position - cgmath::Point3::new(0.0, 2.0, 2.0)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn reparenting_moves_children() {
+        let mut world = world();
+        let a = world.create_entity().with(Transform::from_translation((1.0, 0.0, 0.0).into())).build();
+        let b = world.create_entity().with(Transform::from_translation((0.0, 5.0, 0.0).into())).build();
+        let child = world.create_entity()
+            .with(Transform::from_translation((0.0, 0.0, 1.0).into()))
+            .with(Parent(a))
+            .build();
+        TransformSystem::default().run_now(&world);
+        assert_eq!(global(&world, child), translation(1.0, 0.0, 1.0));
+
+        world.write_storage::<Parent>().insert(child, Parent(b)).unwrap();
+        TransformSystem::default().run_now(&world);
+        assert_eq!(global(&world, child), translation(0.0, 5.0, 1.0));
+
+        world.write_storage::<Parent>().remove(child);
+        TransformSystem::default().run_now(&world);
+        assert_eq!(global(&world, child), translation(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn orphans_become_roots() {
+        let mut world = world();
+        let parent = world.create_entity().with(Transform::from_translation((1.0, 0.0, 0.0).into())).build();
+        let child = world.create_entity()
+            .with(Transform::from_translation((0.0, 1.0, 0.0).into()))
+            .with(Parent(parent))
+            .build();
+        world.delete_entity(parent).unwrap();
+        world.maintain();
+
+        TransformSystem::default().run_now(&world);
+
+        assert_eq!(global(&world, child), translation(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn cycles_are_broken() {
+        let mut world = world();
+        let a = world.create_entity().with(Transform::from_translation((1.0, 0.0, 0.0).into())).build();
+        let b = world.create_entity()
+            .with(Transform::from_translation((0.0, 1.0, 0.0).into()))
+            .with(Parent(a))
+            .build();
+        world.write_storage::<Parent>().insert(a, Parent(b)).unwrap();
+
+        let mut system = TransformSystem::default();
+        system.run_now(&world);
+
+        // Walking up from a finds the cycle at b, which becomes the root
+        assert_eq!(global(&world, b), translation(0.0, 1.0, 0.0));
+        assert_eq!(global(&world, a), translation(1.0, 1.0, 0.0));
+        let cycle_roots: Vec<_> = system.cycle_roots.iter().cloned().collect();
+        assert_eq!(cycle_roots, vec![b]);
+
+        // The cycle is remembered until it's gone
+        system.run_now(&world);
+        assert!(system.cycle_roots.contains(&b));
+        world.write_storage::<Parent>().remove(a);
+        system.run_now(&world);
+        assert!(system.cycle_roots.is_empty());
+    }
+
+    #[test]
+    fn global_transforms_go_with_their_transforms() {
+        let mut world = world();
+        let entity = world.create_entity().with(Transform::default()).build();
+        TransformSystem::default().run_now(&world);
+        assert!(world.read_storage::<GlobalTransform>().get(entity).is_some());
+
+        world.write_storage::<Transform>().remove(entity);
+        TransformSystem::default().run_now(&world);
+        assert!(world.read_storage::<GlobalTransform>().get(entity).is_none());
+    }
+
+    #[test]
+    fn mesh_instances_prefer_global_transforms() {
+        let mut world = world();
+        let mesh = world.write_resource::<Assets<Mesh>>().add(Mesh::default());
+        let parent = world.create_entity().with(Transform::from_translation((1.0, 0.0, 0.0).into())).build();
+        world.create_entity()
+            .with(Transform::from_translation((0.0, 1.0, 0.0).into()))
+            .with(Parent(parent))
+            .with(MeshHandle(mesh))
+            .build();
+
+        TransformSystem::default().run_now(&world);
+        MeshInstanceSystem.run_now(&world);
+
+        assert_eq!(world.read_resource::<Vec<MeshInstance>>()[0].model, translation(1.0, 1.0, 0.0));
+    }
+}
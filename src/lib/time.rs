@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+/// How the `App` turns the time between frames into updates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestepMode {
+    /// Every update simulates exactly this much time. Leftover time carries
+    /// over to the next frame.
+    Fixed(Duration),
+    /// One update per frame, simulating however long the frame took.
+    Variable,
+    /// Splits the frame time into updates no longer than this.
+    SemiFixed(Duration),
+}
+
+/// Decides how many updates to run each frame, and how long they are.
+#[derive(Debug)]
+pub struct Timestep {
+    mode: TimestepMode,
+    max_updates: u32,
+    accumulator: Duration,
+}
+
+impl Timestep {
+    pub fn new(mode: TimestepMode) -> Self {
+        Self {
+            mode,
+            max_updates: 5,
+            accumulator: Duration::from_secs(0),
+        }
+    }
+
+    /// Limits how many updates a single frame can run. When the simulation
+    /// can't keep up, the time it couldn't get to is dropped instead of
+    /// making the next frame even slower.
+    pub fn with_max_updates(mut self, max_updates: u32) -> Self {
+        self.max_updates = max_updates;
+        self
+    }
+
+    pub fn mode(&self) -> TimestepMode {
+        self.mode
+    }
+
+    /// Adds the real time a frame took, and returns the length of each
+    /// update to run for it.
+    pub fn advance(&mut self, frame_time: Duration) -> Vec<Duration> {
+        let mut steps = Vec::new();
+        match self.mode {
+            TimestepMode::Fixed(step) => {
+                self.accumulator += frame_time;
+                while self.accumulator >= step && steps.len() < self.max_updates as usize {
+                    steps.push(step);
+                    self.accumulator -= step;
+                }
+                if self.accumulator >= step {
+                    debug!("Dropping {:?} of simulation time", self.accumulator);
+                    self.accumulator = Duration::from_nanos((self.accumulator.as_nanos() % step.as_nanos()) as u64);
+                }
+            }
+            TimestepMode::Variable => {
+                if frame_time > Duration::from_secs(0) {
+                    steps.push(frame_time);
+                }
+            }
+            TimestepMode::SemiFixed(max_step) => {
+                let mut remaining = frame_time;
+                while remaining > Duration::from_secs(0) && steps.len() < self.max_updates as usize {
+                    let step = remaining.min(max_step);
+                    steps.push(step);
+                    remaining -= step;
+                }
+            }
+        }
+        steps
+    }
+
+    /// How far between two fixed updates the current frame is. Only fixed
+    /// timesteps leave time over, so this is always 1.0 for the others.
+    pub fn alpha(&self) -> f32 {
+        match self.mode {
+            TimestepMode::Fixed(step) => self.accumulator.as_secs_f32() / step.as_secs_f32(),
+            TimestepMode::Variable | TimestepMode::SemiFixed(_) => 1.0,
+        }
+    }
+}
+
+/// The simulation clock, available to systems as a resource. The time scale
+/// only changes how much time each update simulates, not how often updates
+/// happen, so a time scale of 0.0 pauses the simulation while systems keep
+/// seeing input.
+#[derive(Debug, Clone, Copy)]
+pub struct Time {
+    delta: Duration,
+    elapsed: Duration,
+    ticks: u64,
+    time_scale: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            delta: Duration::from_secs(0),
+            elapsed: Duration::from_secs(0),
+            ticks: 0,
+            time_scale: 1.0,
+        }
+    }
+}
+
+impl Time {
+    /// The scaled time simulated by the current update.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// The scaled time simulated since the start.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// How many updates have run.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        assert!(time_scale >= 0.0, "time_scale can't be negative");
+        self.time_scale = time_scale;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.time_scale == 0.0
+    }
+
+    /// Starts an update that lasts `step` of unscaled time.
+    pub fn advance(&mut self, step: Duration) {
+        self.delta = Duration::from_nanos((step.as_nanos() as f64 * self.time_scale as f64).round() as u64);
+        self.elapsed += self.delta;
+        self.ticks += 1;
+    }
+}
+
+/// The real time between the last two frames, available to systems as a
+/// resource. Systems that run every frame, rather than every update, move
+/// things by this instead of `Time`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTime {
+    delta: Duration,
+}
+
+impl FrameTime {
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Starts a frame that came `delta` after the last one.
+    pub fn advance(&mut self, delta: Duration) {
+        self.delta = delta;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn fixed_carries_over_leftover_time() {
+        let mut timestep = Timestep::new(TimestepMode::Fixed(ms(20)));
+        assert_eq!(timestep.advance(ms(30)), vec![ms(20)]);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(ms(10)), vec![ms(20)]);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn fixed_catch_up_is_capped() {
+        let mut timestep = Timestep::new(TimestepMode::Fixed(ms(20))).with_max_updates(3);
+        assert_eq!(timestep.advance(ms(1010)).len(), 3);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(timestep.advance(ms(0)).len(), 0);
+    }
+
+    #[test]
+    fn variable_uses_frame_time() {
+        let mut timestep = Timestep::new(TimestepMode::Variable);
+        assert_eq!(timestep.advance(ms(7)), vec![ms(7)]);
+        assert_eq!(timestep.advance(ms(0)), vec![]);
+        assert_eq!(timestep.alpha(), 1.0);
+    }
+
+    #[test]
+    fn semi_fixed_splits_long_frames() {
+        let mut timestep = Timestep::new(TimestepMode::SemiFixed(ms(10)));
+        assert_eq!(timestep.advance(ms(25)), vec![ms(10), ms(10), ms(5)]);
+        assert_eq!(timestep.advance(ms(4)), vec![ms(4)]);
+    }
+
+    #[test]
+    fn semi_fixed_catch_up_is_capped() {
+        let mut timestep = Timestep::new(TimestepMode::SemiFixed(ms(10))).with_max_updates(2);
+        assert_eq!(timestep.advance(ms(100)), vec![ms(10), ms(10)]);
+    }
+
+    #[test]
+    fn time_accumulates() {
+        let mut time = Time::default();
+        time.advance(ms(20));
+        time.advance(ms(20));
+        assert_eq!(time.ticks(), 2);
+        assert_eq!(time.delta(), ms(20));
+        assert_eq!(time.elapsed(), ms(40));
+    }
+
+    #[test]
+    fn time_scale_scales_delta() {
+        let mut time = Time::default();
+        time.set_time_scale(0.5);
+        time.advance(ms(20));
+        assert_eq!(time.delta(), ms(10));
+
+        time.set_time_scale(0.0);
+        time.advance(ms(20));
+        assert!(time.is_paused());
+        assert_eq!(time.delta(), ms(0));
+        assert_eq!(time.elapsed(), ms(10));
+        assert_eq!(time.ticks(), 2);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::lib::shader::ShaderError;
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 #[allow(unused)]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -16,21 +18,26 @@ pub fn cast_slice<T>(data: &[T]) -> &[u8] {
 }
 
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ShaderStage {
     Vertex,
     Fragment,
     Compute,
 }
 
-pub fn load_glsl(code: &str, stage: ShaderStage) -> Vec<u32> {
+/// Compiles GLSL to SPIR-V. `name` is only used to describe errors, and is
+/// usually the path of the source file.
+pub fn load_glsl(name: &str, code: &str, stage: ShaderStage) -> Result<Vec<u32>, ShaderError> {
     let ty = match stage {
         ShaderStage::Vertex => glsl_to_spirv::ShaderType::Vertex,
         ShaderStage::Fragment => glsl_to_spirv::ShaderType::Fragment,
         ShaderStage::Compute => glsl_to_spirv::ShaderType::Compute,
     };
 
-    let compiled = glsl_to_spirv::compile(&code, ty).unwrap();
-    wgpu::read_spirv(compiled).unwrap()
+    let compiled = glsl_to_spirv::compile(&code, ty)
+        .map_err(|output| ShaderError::from_output(name, stage, &output))?;
+    wgpu::read_spirv(compiled)
+        .map_err(|e| ShaderError::from_output(name, stage, &format!("invalid SPIR-V: {}", e)))
 }
 
 pub fn angle_to_vec2(angle: f32) -> cgmath::Vector2<f32> {
@@ -57,3 +64,31 @@ pub fn rand_vec3(min: f32, max: f32) -> cgmath::Vector3<f32> {
     )
 }
 
+
+/// A directory of its own for a test, which is removed again when this is
+/// dropped.
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// Creates an empty directory. `name` keeps tests that run at the same
+    /// time apart, so it should be unique.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("wgpu-specs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
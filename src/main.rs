@@ -6,33 +6,27 @@ extern crate specs_derive;
 mod lib;
 
 use lib::{
-    graphics::{self, *},
-    camera,
+    actions::{ActionMap, ReloadBindingsSystem},
+    app::AppBuilder,
+    assets::{Assets, Texture},
+    camera::Bounds,
+    components::*,
+    graphics::*,
+    input::InputState,
+    systems::{InstanceUpdateSystem, TextureAtlasSystem},
+    time::Time,
     util,
 };
 use specs::prelude::*;
-use winit::{
-    event,
-    event_loop::{ControlFlow, EventLoop},
-};
-
-#[derive(Debug, Clone, Copy, Component)]
-#[storage(VecStorage)]
-struct Position(cgmath::Vector2<f32>);
 
 #[derive(Debug, Clone, Copy, Component)]
 #[storage(VecStorage)]
 struct Velocity(cgmath::Vector2<f32>);
 
-#[derive(Debug, Clone, Copy)]
-struct Bounds {
-    min: cgmath::Vector2<f32>,
-    max: cgmath::Vector2<f32>,
-}
+const BINDINGS_PATH: &str = "assets/bindings.ron";
 
 const MS_PER_UPDATE: std::time::Duration = std::time::Duration::from_millis(20);
-const NANOS_PER_SEC: u64 = 1_000_000_000;
-const DT_PER_UPDATE: f32 = (MS_PER_UPDATE.as_secs() as f32) + (MS_PER_UPDATE.as_nanos() as f32) / (NANOS_PER_SEC as f32);
+const SLOW_MOTION_SCALE: f32 = 0.25;
 
 struct MovementSystem;
 impl <'a> System<'a> for MovementSystem {
@@ -40,11 +34,13 @@ impl <'a> System<'a> for MovementSystem {
         WriteStorage<'a, Position>,
         WriteStorage<'a, Velocity>,
         ReadExpect<'a, Bounds>,
+        Read<'a, Time>,
     );
 
-    fn run(&mut self, (mut w_pos, mut w_vel, r_bounds): Self::SystemData) {
+    fn run(&mut self, (mut w_pos, mut w_vel, r_bounds, r_time): Self::SystemData) {
+        let dt = r_time.delta_seconds();
         for (pos, vel) in (&mut w_pos, &mut w_vel).join() {
-            pos.0 += vel.0 * DT_PER_UPDATE;
+            pos.0 += vel.0 * dt;
 
             if pos.0.x < r_bounds.min.x {
                 pos.0.x = r_bounds.min.x;
@@ -65,143 +61,62 @@ impl <'a> System<'a> for MovementSystem {
     }
 }
 
-#[derive(Debug, Clone, Copy, Component)]
-#[storage(VecStorage)]
-struct Appearance {
-    color: cgmath::Vector3<f32>,
-    origin: cgmath::Vector2<f32>,
-    scale: cgmath::Vector2<f32>,
-    rotation: f32,
-}
-
-struct InstanceUpdateSystem;
-impl <'a> System<'a> for InstanceUpdateSystem {
-    type SystemData = (
-        ReadStorage<'a, Position>,
-        ReadStorage<'a, Appearance>,
-        WriteExpect<'a, Vec<Instance>>,
-    );
-
-    fn run(&mut self, (r_pos, r_appearance, mut instances): Self::SystemData) {
-        instances.clear();
-        instances.extend((&r_pos, &r_appearance).join().map(|(pos, appearance)| {
-            Instance {
-                offset: pos.0,
-                origin: appearance.origin,
-                scale: appearance.scale,
-                rotation: appearance.rotation,
-                color: appearance.color,
-            }
-        }));
-    }
-}
-
 fn main() {
     env_logger::init();
 
-    let event_loop = EventLoop::new();
-    let (mut graphics, _window) = Graphics::windowed("wgpu-specs", &event_loop);
-    
-    let mut quad_renderer = QuadRenderer::new(&mut graphics, 100);
-
-    let mut world = World::new();
-    world.insert(Vec::<Instance>::with_capacity(100));
-    world.insert(Bounds { min: (-1.0, -1.0).into(), max: (1.0, 1.0).into()});
-
-    let mut dispatcher = DispatcherBuilder::new()
-        .with(MovementSystem, "movement_system", &[])
-        .with(InstanceUpdateSystem, "instance_update_sytem", &["movement_system"])
-        .build();
-    dispatcher.setup(&mut world);
-
-    for _ in 0..10 {
-        let rotation = util::rand(0.0, 2.0 * 3.1415);
-        world.create_entity()
-            .with(Position(cgmath::Zero::zero()))
-            .with(Velocity(util::angle_to_vec2(rotation) * 0.2))
-            .with(Appearance {
-                scale: util::rand_vec2(0.1, 0.2),
-                origin: cgmath::Zero::zero(),
-                rotation,
-                color: util::rand_vec3(0.0, 1.0),
-            })
-            .build();
-    }
-    world.maintain();
-
-    let mut time = std::time::Instant::now();
-    let mut lag = std::time::Duration::from_millis(0);
-
-    event_loop.run(move |event, _, control_flow| {
-        * control_flow = if cfg!(feature = "metal-auto-capture") { 
-            ControlFlow::Exit
-        } else {
-            ControlFlow::Poll
-        };
-
-        match event {
-            event::Event::WindowEvent {
-                event: event::WindowEvent::Resized(size), ..
-            } => {
-                graphics.resize(size);
-            }
-            event::Event::WindowEvent { event, .. } => match event {
-                event::WindowEvent::KeyboardInput {
-                    input: event::KeyboardInput {
-                        virtual_keycode: Some(event::VirtualKeyCode::Escape),
-                        state: event::ElementState::Pressed,
-                        ..
-                    },
-                    ..
-                } | event::WindowEvent::CloseRequested => {
-                    *control_flow = ControlFlow::Exit;
-                }
-                _ => {
-                    // todo: pass the event to the world
-                }
+    let action_map = ActionMap::load(BINDINGS_PATH).unwrap_or_else(|e| {
+        error!("Unable to load {}, using the default bindings: {}", BINDINGS_PATH, e);
+        include_str!("../assets/bindings.ron").parse::<ActionMap>().unwrap()
+    });
+
+    AppBuilder::new("wgpu-specs")
+        .with_timestep(MS_PER_UPDATE)
+        .with_resource(Vec::<Instance>::with_capacity(100))
+        .with_resource(action_map)
+        .with_system(ReloadBindingsSystem::default(), "reload_bindings_system", &[])
+        .with_system(MovementSystem, "movement_system", &[])
+        .with_render_system(TextureAtlasSystem::default(), "texture_atlas_system", &[])
+        .with_render_system(InstanceUpdateSystem, "instance_update_system", &["texture_atlas_system"])
+        .with_renderer(|graphics| QuadRenderer::new(graphics, 100))
+        .on_start(|ctx| {
+            let texture = ctx.world.write_resource::<Assets<Texture>>()
+                .load("rust.png")
+                .map_err(|e| error!("{}", e))
+                .ok();
+            for _ in 0..10 {
+                let rotation = util::rand(0.0, 2.0 * 3.1415);
+                ctx.world.create_entity()
+                    .with(Position(cgmath::Zero::zero()))
+                    .with(Velocity(util::angle_to_vec2(rotation) * 0.2))
+                    .with(Appearance {
+                        scale: util::rand_vec2(0.1, 0.2),
+                        origin: cgmath::Zero::zero(),
+                        rotation,
+                        color: util::rand_vec3(0.0, 1.0),
+                        sprite: None,
+                        texture: texture.clone(),
+                    })
+                    .build();
             }
-            event::Event::EventsCleared => {
-                let mut should_update = false;
-                let elapsed = time.elapsed();
-                time = std::time::Instant::now();
-                lag += elapsed;
-
-                // _window.set_title(&format!("elapsed = {:?}", elapsed));
-
-                while lag >= MS_PER_UPDATE {
-                    dispatcher.dispatch(&world);
-                    world.maintain();
-                    lag -= MS_PER_UPDATE;
-                    should_update = true;
+        })
+        .on_fixed_update(|ctx| {
+            let quit = {
+                let action_map = ctx.world.read_resource::<ActionMap>();
+                let input = ctx.world.read_resource::<InputState>();
+                let mut time = ctx.world.write_resource::<Time>();
+                if action_map.is_just_pressed(&input, "pause") {
+                    let time_scale = if time.is_paused() { 1.0 } else { 0.0 };
+                    time.set_time_scale(time_scale);
                 }
-                if should_update {
-                    quad_renderer.update(&mut graphics, &world.read_resource::<Vec<Instance>>());
+                if action_map.is_just_pressed(&input, "slow_motion") {
+                    let time_scale = if time.time_scale() == SLOW_MOTION_SCALE { 1.0 } else { SLOW_MOTION_SCALE };
+                    time.set_time_scale(time_scale);
                 }
-
-                let frame = graphics.swap_chain.get_next_texture();
-                let mut encoder = graphics.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
-                {
-                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                            attachment: &frame.view,
-                            resolve_target: None,
-                            load_op: wgpu::LoadOp::Clear,
-                            store_op: wgpu::StoreOp::Store,
-                            clear_color: wgpu::Color {
-                                r: 0.1,
-                                g: 0.2,
-                                b: 0.3,
-                                a: 1.0,
-                            },
-                        }],
-                        depth_stencil_attachment: None,
-                    });
-                    quad_renderer.draw(&mut rpass);
-                }
-                
-                graphics.device.get_queue().submit(&[encoder.finish()]);
+                action_map.is_down(&input, "quit")
+            };
+            if quit {
+                ctx.exit();
             }
-            _ => (),
-        }
-    })
-}
\ No newline at end of file
+        })
+        .run()
+}